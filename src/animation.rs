@@ -0,0 +1,495 @@
+use std::collections::HashMap;
+
+use bevy::{
+    asset::{AssetLoader, AssetPath, HandleId, LoadContext, LoadState, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    render::texture::ImageSampler,
+    sprite::Anchor,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use crate::components::{Attacking, Collider, Direction, Player, PlayerState, RigidBody};
+
+pub const KNIGHT_ANIMATIONS: &str = "characters/knight.anim.ron";
+
+/// plays sprite animations from `.anim.ron` files, see `AnimationSet`
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<AnimationSet>()
+            .init_asset_loader::<AnimationSetLoader>()
+            .add_event::<AnimationEvent>()
+            .add_system(build_animation_atlases)
+            .add_system(player_animation)
+            .add_system(animate_sprites.after(player_animation));
+    }
+}
+
+/// a sequence of frames, each shown for the same time
+#[derive(Clone, Debug, Deserialize)]
+pub struct AnimationClip {
+    /// indices into the set's frames
+    pub frames: Vec<usize>,
+    pub frame_duration: f32,
+    /// start again after the last frame, otherwise stay on it
+    #[serde(default = "default_looping")]
+    pub looping: bool,
+    /// names of events sent when reaching a step of the clip, by position in `frames`
+    #[serde(default)]
+    pub events: HashMap<usize, String>,
+}
+
+fn default_looping() -> bool {
+    true
+}
+
+impl AnimationClip {
+    /// position in `frames` reached `elapsed` seconds after the clip started
+    pub fn step_at(&self, elapsed: f32) -> usize {
+        if self.frames.is_empty() || self.frame_duration <= 0. {
+            return 0;
+        }
+        let step = (elapsed / self.frame_duration) as usize;
+        if self.looping {
+            step % self.frames.len()
+        } else {
+            step.min(self.frames.len() - 1)
+        }
+    }
+
+    /// every step reached after `from` seconds up to `to`, in order, or from the start if the
+    /// clip has only just started. a slow frame can skip over steps, or wrap around a loop
+    pub fn steps_reached(&self, from: Option<f32>, to: f32) -> Vec<usize> {
+        if self.frames.is_empty() {
+            return Vec::new();
+        }
+        if self.frame_duration <= 0. {
+            return if from.is_none() { vec![0] } else { Vec::new() };
+        }
+        let first = match from {
+            Some(from) => (from / self.frame_duration) as usize + 1,
+            None => 0,
+        };
+        let mut last = (to / self.frame_duration) as usize;
+        if !self.looping {
+            last = last.min(self.frames.len() - 1);
+        }
+        (first..=last)
+            .map(|step| step % self.frames.len())
+            .collect()
+    }
+}
+
+/// where the frames of an animation set come from
+#[derive(Clone, Debug, Deserialize)]
+pub enum FrameSource {
+    /// separate images, packed into a texture atlas once they've loaded
+    Images(Vec<String>),
+    /// a sprite sheet of frames the same size, numbered along each row from the top left
+    Sheet {
+        texture: String,
+        tile_size: (f32, f32),
+        columns: usize,
+        rows: usize,
+    },
+}
+
+/// what an `.anim.ron` file holds
+#[derive(Clone, Debug, Deserialize)]
+struct AnimationSetDef {
+    frames: FrameSource,
+    clips: HashMap<String, AnimationClip>,
+}
+
+/// a character's animations, which any number of entities can play with an `Animation`
+#[derive(Debug, TypeUuid)]
+#[uuid = "5f0c5a4e-2a8b-4d0c-9a53-7c2f6b1e9d41"]
+pub struct AnimationSet {
+    pub clips: HashMap<String, AnimationClip>,
+    /// the frame images, while they still need packing into `atlas`
+    images: Vec<Handle<Image>>,
+    /// every frame, once they've loaded
+    atlas: Option<Handle<TextureAtlas>>,
+    /// where each frame is in the atlas
+    indices: Vec<usize>,
+}
+
+impl AnimationSet {
+    /// index in the atlas of the frame shown for a step of a clip
+    fn atlas_index(&self, clip: &AnimationClip, step: usize) -> Option<usize> {
+        clip.frames
+            .get(step)
+            .and_then(|frame| self.indices.get(*frame))
+            .copied()
+    }
+}
+
+#[derive(Default)]
+struct AnimationSetLoader;
+
+impl AssetLoader for AnimationSetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let def: AnimationSetDef = ron::de::from_bytes(bytes)?;
+            let set = match def.frames {
+                FrameSource::Images(paths) => {
+                    let paths: Vec<AssetPath<'static>> = paths
+                        .into_iter()
+                        .map(|path| AssetPath::new(path.into(), None))
+                        .collect();
+                    let set = AnimationSet {
+                        clips: def.clips,
+                        images: paths
+                            .iter()
+                            .map(|path| load_context.get_handle(path.clone()))
+                            .collect(),
+                        atlas: None,
+                        indices: Vec::new(),
+                    };
+                    LoadedAsset::new(set).with_dependencies(paths)
+                }
+                FrameSource::Sheet {
+                    texture,
+                    tile_size,
+                    columns,
+                    rows,
+                } => {
+                    let path = AssetPath::new(texture.into(), None);
+                    let atlas = TextureAtlas::from_grid(
+                        load_context.get_handle(path.clone()),
+                        Vec2::new(tile_size.0, tile_size.1),
+                        columns,
+                        rows,
+                        None,
+                        None,
+                    );
+                    let atlas = load_context.set_labeled_asset("atlas", LoadedAsset::new(atlas));
+                    let set = AnimationSet {
+                        clips: def.clips,
+                        images: Vec::new(),
+                        atlas: Some(atlas),
+                        indices: (0..columns * rows).collect(),
+                    };
+                    LoadedAsset::new(set).with_dependency(path)
+                }
+            };
+            load_context.set_default_asset(set);
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["anim.ron"]
+    }
+}
+
+/// pack the frames of animation sets into texture atlases, once they've all loaded
+fn build_animation_atlases(
+    asset_server: Res<AssetServer>,
+    mut sets: ResMut<Assets<AnimationSet>>,
+    mut images: ResMut<Assets<Image>>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    let waiting: Vec<HandleId> = sets
+        .iter()
+        .filter(|(_, set)| set.atlas.is_none())
+        .map(|(id, _)| id)
+        .collect();
+    for id in waiting {
+        let set = match sets.get_mut(&Handle::weak(id)) {
+            Some(set) => set,
+            None => continue,
+        };
+        match asset_server.get_group_load_state(set.images.iter().map(|h| h.id())) {
+            LoadState::Loaded => (),
+            LoadState::Failed => {
+                println!("Unable to load the frames of an animation set");
+                // don't try again every frame
+                set.atlas = Some(Handle::default());
+                continue;
+            }
+            _ => continue,
+        }
+
+        let mut builder = TextureAtlasBuilder::default();
+        for handle in set.images.iter() {
+            if let Some(image) = images.get(handle) {
+                builder.add_texture(handle.clone(), image);
+            }
+        }
+        let atlas = match builder.finish(&mut images) {
+            Ok(atlas) => atlas,
+            Err(e) => {
+                println!("Unable to pack the frames of an animation set: {:?}", e);
+                set.atlas = Some(Handle::default());
+                continue;
+            }
+        };
+        // frames are packed right next to each other, so don't blend them together
+        if let Some(image) = images.get_mut(&atlas.texture) {
+            image.sampler_descriptor = ImageSampler::nearest();
+        }
+        set.indices = set
+            .images
+            .iter()
+            .map(|handle| atlas.get_texture_index(handle).unwrap_or(0))
+            .collect();
+        set.atlas = Some(atlases.add(atlas));
+        // the atlas has its own copy of them
+        set.images.clear();
+    }
+}
+
+/// which clip of an animation set a sprite is playing, and for how long
+#[derive(Component)]
+pub struct Animation {
+    pub set: Handle<AnimationSet>,
+    pub clip: String,
+    pub elapsed: f32,
+    // whether the clip has been shown yet, so the events of its first step are sent once
+    started: bool,
+}
+
+impl Animation {
+    pub fn new(set: Handle<AnimationSet>, clip: &str) -> Self {
+        Self {
+            set,
+            clip: clip.to_string(),
+            elapsed: 0.,
+            started: false,
+        }
+    }
+
+    /// whether a clip can be shown, with its set and atlas loaded
+    pub fn can_play(
+        &self,
+        clip: &str,
+        sets: &Assets<AnimationSet>,
+        atlases: &Assets<TextureAtlas>,
+    ) -> bool {
+        match sets.get(&self.set) {
+            Some(set) => {
+                set.clips.contains_key(clip)
+                    && set.atlas.as_ref().and_then(|h| atlases.get(h)).is_some()
+            }
+            None => false,
+        }
+    }
+
+    /// start playing another clip, from the beginning, unless it's already playing
+    pub fn play(&mut self, clip: &str) {
+        if self.clip != clip {
+            self.clip = clip.to_string();
+            self.elapsed = 0.;
+            self.started = false;
+        }
+    }
+}
+
+/// sent when an animation reaches a step with an event, e.g. when an attack is released
+pub struct AnimationEvent {
+    pub entity: Entity,
+    pub name: String,
+}
+
+/// the clip for what a player is doing
+fn player_clip(player: &Player, rigidbody: &RigidBody, attacking: bool) -> &'static str {
+    match player.state {
+        PlayerState::Dead => "hit",
+        _ if attacking => "attack",
+        PlayerState::Jump => "jump",
+        // walking into a wall, or holding on to a ladder, is standing still
+        PlayerState::Walk if rigidbody.speed.x != 0. => "run",
+        PlayerState::Climb if rigidbody.speed.y != 0. => "run",
+        PlayerState::Stand | PlayerState::Walk | PlayerState::Climb => "idle",
+    }
+}
+
+/// pick each player's animation, and face the way they're going
+#[allow(clippy::type_complexity)]
+fn player_animation(
+    mut query: Query<(
+        &Player,
+        &RigidBody,
+        Option<&Attacking>,
+        &mut Animation,
+        &mut TextureAtlasSprite,
+    )>,
+) {
+    for (player, rigidbody, attacking, mut animation, mut sprite) in query.iter_mut() {
+        animation.play(player_clip(player, rigidbody, attacking.is_some()));
+        let flip = player.facing == Direction::Left;
+        if sprite.flip_x != flip {
+            sprite.flip_x = flip;
+        }
+    }
+}
+
+/// show the current frame of each animation, and send the events of the steps reached
+#[allow(clippy::type_complexity)]
+fn animate_sprites(
+    time: Res<Time>,
+    sets: Res<Assets<AnimationSet>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    mut ev_animation: EventWriter<AnimationEvent>,
+    mut query: Query<(
+        Entity,
+        &mut Animation,
+        Option<&Collider>,
+        &mut TextureAtlasSprite,
+        &mut Handle<TextureAtlas>,
+    )>,
+) {
+    for (entity, mut animation, collider, mut sprite, mut handle) in query.iter_mut() {
+        let set = match sets.get(&animation.set) {
+            Some(set) => set,
+            None => continue,
+        };
+        let (atlas_handle, atlas) = match set.atlas.as_ref() {
+            Some(handle) => match atlases.get(handle) {
+                Some(atlas) => (handle, atlas),
+                None => continue,
+            },
+            None => continue,
+        };
+        let clip = match set.clips.get(&animation.clip) {
+            Some(clip) => clip,
+            None => continue,
+        };
+
+        let from = animation.started.then_some(animation.elapsed);
+        animation.elapsed += time.delta_seconds();
+        animation.started = true;
+        for step in clip.steps_reached(from, animation.elapsed) {
+            if let Some(name) = clip.events.get(&step) {
+                ev_animation.send(AnimationEvent {
+                    entity,
+                    name: name.clone(),
+                });
+            }
+        }
+
+        let step = clip.step_at(animation.elapsed);
+
+        let index = match set.atlas_index(clip, step) {
+            Some(index) if index < atlas.textures.len() => index,
+            _ => continue,
+        };
+        if *handle != *atlas_handle {
+            *handle = atlas_handle.clone();
+        }
+        sprite.index = index;
+        // frames are different heights, so stand them all on the bottom of the collider
+        if let Some(collider) = collider {
+            let height = atlas.textures[index].height();
+            sprite.anchor = Anchor::Custom(Vec2::new(0., collider.half.y / height - 0.5));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::asset_path;
+
+    fn clip(looping: bool) -> AnimationClip {
+        AnimationClip {
+            frames: vec![4, 5, 6],
+            frame_duration: 0.25,
+            looping,
+            events: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn clip_steps() {
+        let looping = clip(true);
+        assert_eq!(looping.step_at(0.), 0);
+        assert_eq!(looping.step_at(0.3), 1);
+        assert_eq!(looping.step_at(0.8), 0);
+        let once = clip(false);
+        assert_eq!(once.step_at(0.6), 2);
+        assert_eq!(once.step_at(10.), 2);
+    }
+
+    #[test]
+    fn reached_steps() {
+        let looping = clip(true);
+        assert_eq!(looping.steps_reached(None, 0.), [0]);
+        assert!(looping.steps_reached(Some(0.), 0.1).is_empty());
+        assert_eq!(looping.steps_reached(Some(0.1), 0.3), [1]);
+        // a slow frame skips a step
+        assert_eq!(looping.steps_reached(Some(0.2), 0.6), [1, 2]);
+        assert_eq!(looping.steps_reached(None, 0.6), [0, 1, 2]);
+        // and wraps around the loop
+        assert_eq!(looping.steps_reached(Some(0.6), 1.1), [0, 1]);
+        let once = clip(false);
+        assert_eq!(once.steps_reached(Some(0.3), 10.), [2]);
+        assert!(once.steps_reached(Some(0.6), 10.).is_empty());
+    }
+
+    #[test]
+    fn atlas_indices() {
+        let set = AnimationSet {
+            clips: HashMap::new(),
+            images: Vec::new(),
+            atlas: None,
+            indices: vec![9, 8, 7, 6, 5, 4, 3],
+        };
+        assert_eq!(set.atlas_index(&clip(true), 0), Some(5));
+        assert_eq!(set.atlas_index(&clip(true), 2), Some(3));
+        assert_eq!(set.atlas_index(&clip(true), 3), None);
+    }
+
+    #[test]
+    fn knight() {
+        let contents = std::fs::read_to_string(asset_path(KNIGHT_ANIMATIONS)).unwrap();
+        let def: AnimationSetDef = ron::from_str(&contents).unwrap();
+        let frames = match def.frames {
+            FrameSource::Images(frames) => frames.len(),
+            FrameSource::Sheet { columns, rows, .. } => columns * rows,
+        };
+        // every clip the player uses, only showing frames that exist
+        for name in ["idle", "run", "jump", "hit", "attack"] {
+            let clip = &def.clips[name];
+            assert!(!clip.frames.is_empty());
+            assert!(clip.frames.iter().all(|frame| *frame < frames));
+        }
+        assert_eq!(
+            def.clips["run"].events.get(&1).map(|s| s.as_str()),
+            Some("footstep")
+        );
+        // the attack fires, then ends, once
+        let attack = &def.clips["attack"];
+        assert!(!attack.looping);
+        let mut events: Vec<_> = attack.events.iter().collect();
+        events.sort();
+        let names: Vec<&str> = events.iter().map(|(_, name)| name.as_str()).collect();
+        assert_eq!(names, ["attack release", "attack end"]);
+        assert_eq!(*events[1].0, attack.frames.len() - 1);
+    }
+
+    #[test]
+    fn player_clips() {
+        let mut player = Player::default();
+        let mut rigidbody = RigidBody::from_transform(Transform::default());
+        assert_eq!(player_clip(&player, &rigidbody, false), "idle");
+        player.state = PlayerState::Walk;
+        assert_eq!(player_clip(&player, &rigidbody, false), "idle");
+        rigidbody.speed.x = -240.;
+        assert_eq!(player_clip(&player, &rigidbody, false), "run");
+        player.state = PlayerState::Jump;
+        assert_eq!(player_clip(&player, &rigidbody, false), "jump");
+        assert_eq!(player_clip(&player, &rigidbody, true), "attack");
+        player.state = PlayerState::Dead;
+        assert_eq!(player_clip(&player, &rigidbody, false), "hit");
+        assert_eq!(player_clip(&player, &rigidbody, true), "hit");
+    }
+}
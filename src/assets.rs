@@ -0,0 +1,8 @@
+use std::path::PathBuf;
+
+use bevy::asset::FileAssetIo;
+
+/// path on disk of a file in the assets folder, resolved the same way the `AssetServer` does
+pub fn asset_path(path: &str) -> PathBuf {
+    FileAssetIo::get_base_path().join("assets").join(path)
+}
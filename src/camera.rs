@@ -0,0 +1,559 @@
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    prelude::*,
+    render::{
+        camera::{ScalingMode, Viewport},
+        view::RenderLayers,
+    },
+    transform::TransformSystem,
+    window::{WindowMode, WindowResized, WindowScaleFactorChanged},
+};
+
+use crate::{
+    components::{Direction, Player},
+    constants::SPRITE_SCALE,
+    map::{LevelLoaded, Map},
+    settings::{CameraMode, Settings},
+};
+
+// the furthest the shared camera zooms out, and the space it keeps around the players
+const MAX_ZOOM: f32 = 2.;
+const ZOOM_MARGIN: f32 = 96.;
+// split screen is at most 2x2
+const MAX_VIEWPORTS: usize = 4;
+// only seen by the ui camera
+const UI_LAYER: u8 = RenderLayers::TOTAL_LAYERS as u8 - 1;
+// how much of the world is shown, in world units. windows are filled with it scaled so sprite
+// pixels are a whole number of physical pixels, with bars around whatever's left over
+pub const VIRTUAL_SIZE: Vec2 = Vec2::new(1280., 720.);
+
+/// cameras following the players, shared or split screen depending on `Settings::camera_mode`
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Screen>()
+            .add_startup_system(spawn_ui_camera)
+            .add_system(toggle_camera_mode)
+            .add_system(toggle_fullscreen)
+            .add_system(update_screen)
+            .add_system(
+                manage_cameras
+                    .after(toggle_camera_mode)
+                    .after(update_screen),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                follow_players.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// a camera which keeps players in view, without leaving the map
+#[derive(Component)]
+pub struct FollowCamera {
+    /// which player this camera follows in split screen
+    pub index: usize,
+    /// how far the players can move from the point being followed before it moves too
+    pub dead_zone: Vec2,
+    /// how quickly the camera catches up, higher is faster
+    pub smoothing: f32,
+    /// how far ahead of a player, in the direction they're facing, the camera looks
+    pub look_ahead: f32,
+    focus: Option<Vec2>,
+    ahead: f32,
+    // where the camera is and how far it's zoomed out, before any camera effects
+    position: Vec2,
+    zoom: f32,
+}
+
+impl FollowCamera {
+    fn new(index: usize) -> Self {
+        Self {
+            index,
+            dead_zone: Vec2::new(48., 32.),
+            smoothing: 5.,
+            look_ahead: 96.,
+            focus: None,
+            ahead: 0.,
+            position: Vec2::ZERO,
+            zoom: 1.,
+        }
+    }
+}
+
+/// the window's size, kept up to date as it's resized
+#[derive(Resource, Default)]
+pub struct Screen {
+    /// physical size of the window
+    pub size: UVec2,
+    /// physical pixels per world unit, a whole number per sprite pixel so pixel art stays crisp
+    /// unless the window is smaller than `VIRTUAL_SIZE`
+    pub pixel_scale: f32,
+    /// physical position and size of the part of the window the game is drawn in
+    pub area: (UVec2, UVec2),
+    /// physical pixels per logical pixel, which ui is laid out in
+    pub scale_factor: f32,
+}
+
+impl Screen {
+    /// logical size of the bars left and above the game area, then right and below it, for
+    /// laying ui out inside of it
+    pub fn bars(&self) -> (Vec2, Vec2) {
+        let (origin, size) = self.area;
+        let after = self.size.max(origin + size) - origin - size;
+        (
+            origin.as_vec2() / self.scale_factor,
+            after.as_vec2() / self.scale_factor,
+        )
+    }
+}
+
+/// physical pixels per world unit for the largest whole number of physical pixels per sprite
+/// pixel that fits `VIRTUAL_SIZE` in the window, or whatever fraction fits for smaller windows
+fn pixel_scale(window: UVec2) -> f32 {
+    let fit = (window.as_vec2() / VIRTUAL_SIZE).min_element() * SPRITE_SCALE;
+    if fit >= 1. {
+        fit.floor() / SPRITE_SCALE
+    } else {
+        fit / SPRITE_SCALE
+    }
+}
+
+/// `VIRTUAL_SIZE` scaled up and centered in the window, letterboxed on the sides that don't fit
+fn game_area(window: UVec2, pixel_scale: f32) -> (UVec2, UVec2) {
+    let size = (VIRTUAL_SIZE * pixel_scale)
+        .round()
+        .as_uvec2()
+        .min(window)
+        .max(UVec2::ONE);
+    ((window.max(size) - size) / 2, size)
+}
+
+fn update_screen(
+    windows: Res<Windows>,
+    mut screen: ResMut<Screen>,
+    mut ev_resized: EventReader<WindowResized>,
+    mut ev_scale_factor: EventReader<WindowScaleFactorChanged>,
+) {
+    let changed = ev_resized.iter().count() + ev_scale_factor.iter().count() > 0;
+    if !changed && screen.size != UVec2::ZERO {
+        return;
+    }
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let size = UVec2::new(window.physical_width(), window.physical_height());
+    let scale_factor = window.scale_factor() as f32;
+    if size == screen.size && scale_factor == screen.scale_factor {
+        return;
+    }
+    screen.size = size;
+    screen.pixel_scale = pixel_scale(size);
+    screen.area = game_area(size, screen.pixel_scale);
+    screen.scale_factor = scale_factor;
+}
+
+fn toggle_fullscreen(kb: Res<Input<KeyCode>>, mut windows: ResMut<Windows>) {
+    if !kb.just_pressed(KeyCode::F11) {
+        return;
+    }
+    if let Some(window) = windows.get_primary_mut() {
+        window.set_mode(match window.mode() {
+            WindowMode::Windowed => WindowMode::BorderlessFullscreen,
+            _ => WindowMode::Windowed,
+        });
+    }
+}
+
+/// the render layer only this camera sees, for sprites drawn per camera like the light map
+#[derive(Component, Clone, Copy)]
+pub struct CameraLayer(pub u8);
+
+/// draws the ui over the whole window, whatever the game cameras are doing
+#[derive(Component)]
+struct UiCamera;
+
+fn spawn_ui_camera(mut commands: Commands) {
+    commands.spawn((
+        UiCamera,
+        Camera2dBundle {
+            camera: Camera {
+                // after the game cameras
+                priority: MAX_VIEWPORTS as isize,
+                ..default()
+            },
+            camera_2d: Camera2d {
+                clear_color: ClearColorConfig::None,
+            },
+            ..default()
+        },
+        RenderLayers::layer(UI_LAYER),
+    ));
+}
+
+fn toggle_camera_mode(kb: Res<Input<KeyCode>>, mut settings: ResMut<Settings>) {
+    if kb.just_pressed(KeyCode::F2) {
+        settings.camera_mode = match settings.camera_mode {
+            CameraMode::Shared => CameraMode::Split,
+            CameraMode::Split => CameraMode::Shared,
+        };
+        println!("Camera mode {:?}", settings.camera_mode);
+    }
+}
+
+/// physical position and size of each viewport when splitting the game area between players
+///
+/// two players get half each side by side, three or four a quarter each.
+fn split_viewports(count: usize, area: (UVec2, UVec2)) -> Vec<(UVec2, UVec2)> {
+    let (origin, size) = area;
+    match count {
+        0 | 1 => vec![area],
+        2 => {
+            let size = UVec2::new(size.x / 2, size.y);
+            vec![(origin, size), (origin + UVec2::new(size.x, 0), size)]
+        }
+        _ => {
+            let size = size / 2;
+            (0..count.min(MAX_VIEWPORTS) as u32)
+                .map(|i| (origin + UVec2::new(i % 2, i / 2) * size, size))
+                .collect()
+        }
+    }
+}
+
+/// a camera per player in split screen, otherwise just one, and their viewports
+fn manage_cameras(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    screen: Res<Screen>,
+    player_query: Query<(), With<Player>>,
+    mut camera_query: Query<(
+        Entity,
+        &FollowCamera,
+        &mut Camera,
+        &mut OrthographicProjection,
+    )>,
+) {
+    if screen.size == UVec2::ZERO {
+        return;
+    }
+    let wanted = match settings.camera_mode {
+        CameraMode::Shared => 1,
+        CameraMode::Split => player_query.iter().count().clamp(1, MAX_VIEWPORTS),
+    };
+    let viewports = split_viewports(wanted, screen.area);
+    // a world unit is always the same whole number of pixels, whatever the window's size
+    let view_half = |index: usize| viewports[index].1.as_vec2() / (screen.pixel_scale * 2.);
+
+    let mut existing = vec![false; wanted];
+    for (entity, follow, mut camera, mut projection) in camera_query.iter_mut() {
+        if follow.index >= wanted {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        existing[follow.index] = true;
+
+        // the shared camera is letterboxed too
+        let (position, size) = viewports[follow.index];
+        let viewport = Some(Viewport {
+            physical_position: position,
+            physical_size: size,
+            ..default()
+        });
+        // `Viewport` isn't comparable, and only changes should mark the camera as changed
+        let rect = |viewport: &Option<Viewport>| {
+            viewport
+                .as_ref()
+                .map(|v| (v.physical_position, v.physical_size))
+        };
+        if rect(&camera.viewport) != rect(&viewport) {
+            camera.viewport = viewport;
+        }
+
+        let half = view_half(follow.index);
+        if Vec2::new(projection.right, projection.top) != half {
+            projection.left = -half.x;
+            projection.right = half.x;
+            projection.bottom = -half.y;
+            projection.top = half.y;
+        }
+    }
+
+    for (index, _) in existing.iter().enumerate().filter(|(_, exists)| !**exists) {
+        let layer = index as u8 + 1;
+        let half = view_half(index);
+        commands.spawn((
+            FollowCamera::new(index),
+            CameraLayer(layer),
+            Camera2dBundle {
+                camera: Camera {
+                    priority: index as isize,
+                    ..default()
+                },
+                camera_2d: Camera2d {
+                    // clearing clears the whole window, not just the viewport, so only the
+                    // first camera does it
+                    clear_color: if index == 0 {
+                        ClearColorConfig::Default
+                    } else {
+                        ClearColorConfig::None
+                    },
+                },
+                projection: OrthographicProjection {
+                    left: -half.x,
+                    right: half.x,
+                    bottom: -half.y,
+                    top: half.y,
+                    // kept up to date with the viewport here instead
+                    scaling_mode: ScalingMode::None,
+                    ..default()
+                },
+                ..default()
+            },
+            RenderLayers::from_layers(&[0, layer]),
+            UiCameraConfig { show_ui: false },
+        ));
+    }
+}
+
+/// moves `focus` as little as possible to keep `target` within `dead_zone` of it
+fn follow(focus: Vec2, target: Vec2, dead_zone: Vec2) -> Vec2 {
+    focus + (target - focus - (target - focus).clamp(-dead_zone, dead_zone))
+}
+
+/// the projection scale needed to fit an area in a view, with a margin around it
+fn zoom_to_fit(extent: Vec2, view_half: Vec2) -> f32 {
+    ((extent + ZOOM_MARGIN * 2.) / (view_half * 2.))
+        .max_element()
+        .clamp(1., MAX_ZOOM)
+}
+
+/// the closest point to `center` that keeps a view of the map from showing anything outside it
+///
+/// maps smaller than the view are centered instead.
+fn clamp_to_map(map: &Map, center: Vec2, view_half: Vec2) -> Vec2 {
+    let (min, max) = map.bounds();
+    let clamp = |center: f32, min: f32, max: f32, half: f32| {
+        if max - min <= half * 2. {
+            (min + max) / 2.
+        } else {
+            center.clamp(min + half, max - half)
+        }
+    };
+    Vec2::new(
+        clamp(center.x, min.x, max.x, view_half.x),
+        clamp(center.y, min.y, max.y, view_half.y),
+    )
+}
+
+/// move towards the players, and jump straight there when a level is loaded
+pub fn follow_players(
+    time: Res<Time>,
+    map: Res<Map>,
+    settings: Res<Settings>,
+    screen: Res<Screen>,
+    mut ev_loaded: EventReader<LevelLoaded>,
+    player_query: Query<(Entity, &Transform, &Player), Without<FollowCamera>>,
+    mut camera_query: Query<(
+        &mut FollowCamera,
+        &mut Transform,
+        &mut OrthographicProjection,
+    )>,
+) {
+    // in a stable order, so each split screen camera keeps following the same player
+    let mut players: Vec<(Entity, Vec2, Direction)> = player_query
+        .iter()
+        .map(|(entity, transform, player)| {
+            (entity, transform.translation.truncate(), player.facing)
+        })
+        .collect();
+    if players.is_empty() {
+        return;
+    }
+    players.sort_by_key(|(entity, _, _)| *entity);
+    let level_loaded = ev_loaded.iter().last().is_some();
+
+    for (mut camera, mut transform, mut projection) in camera_query.iter_mut() {
+        let targets = match (settings.camera_mode, players.get(camera.index)) {
+            (CameraMode::Split, Some(_)) => &players[camera.index..=camera.index],
+            _ => &players[..],
+        };
+        let min = targets
+            .iter()
+            .fold(Vec2::splat(f32::MAX), |min, (_, pos, _)| min.min(*pos));
+        let max = targets
+            .iter()
+            .fold(Vec2::splat(f32::MIN), |max, (_, pos, _)| max.max(*pos));
+        let target = (min + max) / 2.;
+
+        // frame rate independent easing
+        let t = 1. - (-camera.smoothing * time.delta_seconds()).exp();
+        let snap = level_loaded || camera.focus.is_none();
+
+        let unscaled_half = Vec2::new(
+            projection.right - projection.left,
+            projection.top - projection.bottom,
+        ) / 2.;
+        camera.zoom = match settings.camera_mode {
+            CameraMode::Shared => {
+                let zoom = zoom_to_fit(max - min, unscaled_half);
+                if snap {
+                    zoom
+                } else {
+                    camera.zoom + (zoom - camera.zoom) * t
+                }
+            }
+            CameraMode::Split => 1.,
+        };
+        projection.scale = camera.zoom;
+        let view_half = unscaled_half * camera.zoom;
+
+        // looking ahead of a group of players would leave some behind
+        let facing = match targets {
+            [(_, _, Direction::Left)] => -1.,
+            [(_, _, Direction::Right)] => 1.,
+            [_] => camera.ahead.signum(),
+            _ => 0.,
+        };
+        camera.ahead += (facing * camera.look_ahead - camera.ahead) * t;
+
+        let focus = match camera.focus {
+            Some(focus) if !snap => follow(focus, target, camera.dead_zone),
+            _ => target,
+        };
+        camera.focus = Some(focus);
+
+        let wanted = clamp_to_map(&map, focus + Vec2::new(camera.ahead, 0.), view_half);
+        camera.position = if snap {
+            wanted
+        } else {
+            camera.position + (wanted - camera.position) * t
+        };
+        // on a whole pixel, so sprites don't shimmer as the camera moves
+        let pixel = camera.zoom / screen.pixel_scale.max(1.);
+        let snapped = (camera.position / pixel).round() * pixel;
+        transform.translation.x = snapped.x;
+        transform.translation.y = snapped.y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dead_zone() {
+        let zone = Vec2::new(10., 5.);
+        let focus = Vec2::new(100., 100.);
+        assert_eq!(follow(focus, Vec2::new(105., 97.), zone), focus);
+        assert_eq!(
+            follow(focus, Vec2::new(130., 90.), zone),
+            Vec2::new(120., 95.)
+        );
+        assert_eq!(
+            follow(focus, Vec2::new(80., 120.), zone),
+            Vec2::new(90., 115.)
+        );
+    }
+
+    #[test]
+    fn map_bounds() {
+        // 640x320, from -320/-160 to 320/160
+        let mut map = Map::new(20, 10);
+        map.position = Vec3::new(-304., -144., 0.);
+        let view_half = Vec2::new(200., 100.);
+        assert_eq!(clamp_to_map(&map, Vec2::ZERO, view_half), Vec2::ZERO);
+        assert_eq!(
+            clamp_to_map(&map, Vec2::new(500., -500.), view_half),
+            Vec2::new(120., -60.)
+        );
+        // too small for the view: centered
+        assert_eq!(
+            clamp_to_map(&map, Vec2::new(500., 70.), Vec2::new(400., 100.)),
+            Vec2::new(0., 60.)
+        );
+    }
+
+    #[test]
+    fn zoom() {
+        let view_half = Vec2::new(400., 200.);
+        // close together, or a single player
+        assert_eq!(zoom_to_fit(Vec2::ZERO, view_half), 1.);
+        // far enough apart to zoom out
+        assert_eq!(zoom_to_fit(Vec2::new(1008., 0.), view_half), 1.5);
+        assert_eq!(zoom_to_fit(Vec2::new(0., 608.), view_half), 2.);
+        // up to a limit
+        assert_eq!(zoom_to_fit(Vec2::new(5000., 0.), view_half), MAX_ZOOM);
+    }
+
+    #[test]
+    fn pixel_scales() {
+        // a sprite pixel is 2 world units, so at least a physical pixel for every 2 of them
+        assert_eq!(pixel_scale(UVec2::new(640, 360)), 0.5);
+        assert_eq!(pixel_scale(UVec2::new(1280, 720)), 1.);
+        assert_eq!(pixel_scale(UVec2::new(1920, 1080)), 1.5);
+        assert_eq!(pixel_scale(UVec2::new(1366, 768)), 1.);
+        assert_eq!(pixel_scale(UVec2::new(2560, 1440)), 2.);
+        // limited by the height
+        assert_eq!(pixel_scale(UVec2::new(3440, 1440)), 2.);
+        // too small scales down to fit, rather than cropping
+        assert_eq!(pixel_scale(UVec2::new(320, 360)), 0.25);
+    }
+
+    #[test]
+    fn letterboxing() {
+        let window = UVec2::new(1920, 1080);
+        assert_eq!(game_area(window, 1.5), (UVec2::ZERO, window));
+        // bars either side
+        assert_eq!(
+            game_area(UVec2::new(1366, 768), 1.),
+            (UVec2::new(43, 24), UVec2::new(1280, 720))
+        );
+        // and above and below
+        assert_eq!(
+            game_area(UVec2::new(800, 600), 0.5),
+            (UVec2::new(80, 120), UVec2::new(640, 360))
+        );
+        assert_eq!(
+            game_area(UVec2::new(320, 360), 0.25),
+            (UVec2::new(0, 90), UVec2::new(320, 180))
+        );
+    }
+
+    #[test]
+    fn ui_in_game_area() {
+        let mut screen = Screen {
+            size: UVec2::new(1366, 768),
+            pixel_scale: 1.,
+            area: (UVec2::new(43, 24), UVec2::new(1280, 720)),
+            scale_factor: 1.,
+        };
+        assert_eq!(screen.bars(), (Vec2::new(43., 24.), Vec2::new(43., 24.)));
+        // in logical pixels
+        screen.scale_factor = 2.;
+        assert_eq!(screen.bars(), (Vec2::new(21.5, 12.), Vec2::new(21.5, 12.)));
+        // odd leftovers go after
+        screen.size = UVec2::new(1367, 768);
+        screen.scale_factor = 1.;
+        assert_eq!(screen.bars().1, Vec2::new(44., 24.));
+    }
+
+    #[test]
+    fn viewports() {
+        let area = (UVec2::new(40, 20), UVec2::new(1280, 720));
+        assert_eq!(split_viewports(1, area), vec![area]);
+        assert_eq!(
+            split_viewports(2, area),
+            vec![
+                (UVec2::new(40, 20), UVec2::new(640, 720)),
+                (UVec2::new(680, 20), UVec2::new(640, 720))
+            ]
+        );
+        let quarters = split_viewports(3, area);
+        assert_eq!(quarters.len(), 3);
+        assert_eq!(quarters[2], (UVec2::new(40, 380), UVec2::new(640, 360)));
+        assert_eq!(split_viewports(4, area)[3].0, UVec2::new(680, 380));
+    }
+}
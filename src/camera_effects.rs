@@ -0,0 +1,201 @@
+use bevy::{prelude::*, transform::TransformSystem};
+
+use crate::{
+    camera::{follow_players, FollowCamera, Screen},
+    settings::Settings,
+};
+
+// the furthest the view is moved by shaking, in pixels
+pub const MAX_SHAKE_OFFSET: f32 = 12.;
+// how quickly trauma wears off, per second
+const TRAUMA_DECAY: f32 = 1.5;
+// how quickly the view wobbles while shaking
+const SHAKE_FREQUENCY: f32 = 25.;
+// how quickly a zoom punch eases back out, higher is faster
+const PUNCH_RECOVERY: f32 = 8.;
+// seconds a screen flash takes to fade out
+const SCREEN_FLASH_DURATION: f32 = 0.25;
+
+/// shake, zoom punches and flashes on top of whatever the cameras are following
+pub struct CameraEffectsPlugin;
+
+impl Plugin for CameraEffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CameraEffect>()
+            .init_resource::<CameraEffects>()
+            .add_startup_system(setup_screen_flash)
+            .add_system(layout_screen_flash)
+            .add_system(receive_camera_effects)
+            .add_system(update_screen_flash.after(receive_camera_effects))
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                apply_camera_effects
+                    .after(follow_players)
+                    .before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// something that happened which the cameras should show, applied to every camera
+pub enum CameraEffect {
+    /// add trauma, from 0 to 1, which shakes the view more the higher it is
+    Shake(f32),
+    /// zoom in by a fraction of the view, then ease back out
+    ZoomPunch(f32),
+    /// tint the whole screen, fading out from the color's alpha
+    Flash(Color),
+}
+
+/// the state of the camera effects, as they wear off
+#[derive(Resource, Default)]
+pub struct CameraEffects {
+    trauma: f32,
+    punch: f32,
+    flash: Option<(Color, Timer)>,
+}
+
+/// overlay over the game area for `CameraEffect::Flash`
+#[derive(Component)]
+struct ScreenFlash;
+
+fn setup_screen_flash(mut commands: Commands) {
+    commands.spawn((
+        ScreenFlash,
+        NodeBundle {
+            // sized by `layout_screen_flash`
+            style: Style {
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            background_color: Color::NONE.into(),
+            // over the minimap, under the level transition overlay
+            z_index: ZIndex::Global(90),
+            ..default()
+        },
+    ));
+}
+
+/// cover the game area, leaving the bars around it alone
+fn layout_screen_flash(screen: Res<Screen>, mut query: Query<&mut Style, With<ScreenFlash>>) {
+    if !screen.is_changed() || screen.size == UVec2::ZERO {
+        return;
+    }
+    let (before, after) = screen.bars();
+    for mut style in query.iter_mut() {
+        style.position = UiRect::new(
+            Val::Px(before.x),
+            Val::Px(after.x),
+            Val::Px(before.y),
+            Val::Px(after.y),
+        );
+    }
+}
+
+fn receive_camera_effects(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut effects: ResMut<CameraEffects>,
+    mut ev_effects: EventReader<CameraEffect>,
+) {
+    let dt = time.delta_seconds();
+    effects.trauma = (effects.trauma - TRAUMA_DECAY * dt).max(0.);
+    effects.punch *= (-PUNCH_RECOVERY * dt).exp();
+
+    for ev in ev_effects.iter() {
+        match ev {
+            CameraEffect::Shake(trauma) => {
+                effects.trauma = (effects.trauma + trauma).clamp(0., 1.);
+            }
+            CameraEffect::ZoomPunch(amount) => {
+                effects.punch = effects.punch.max(amount.clamp(0., 0.5));
+            }
+            CameraEffect::Flash(color) => {
+                if settings.screen_flashes {
+                    effects.flash = Some((
+                        *color,
+                        Timer::from_seconds(SCREEN_FLASH_DURATION, TimerMode::Once),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn update_screen_flash(
+    time: Res<Time>,
+    mut effects: ResMut<CameraEffects>,
+    mut query: Query<&mut BackgroundColor, With<ScreenFlash>>,
+) {
+    let (color, finished) = match effects.flash.as_mut() {
+        Some((color, timer)) => {
+            timer.tick(time.delta());
+            let mut faded = *color;
+            faded.set_a(color.a() * timer.percent_left());
+            (faded, timer.finished())
+        }
+        None => return,
+    };
+    if finished {
+        effects.flash = None;
+    }
+    for mut background in query.iter_mut() {
+        background.0 = color;
+    }
+}
+
+/// how far the view is moved for some trauma at a point in time
+///
+/// shaking grows with the square of trauma, so small hits barely register and big ones are
+/// violent. the movement is a mix of sine waves rather than random, so it stays smooth.
+fn shake_offset(trauma: f32, strength: f32, time: f32) -> Vec2 {
+    let shake = trauma.clamp(0., 1.).powi(2) * strength.max(0.);
+    let wobble = |seed: f32| {
+        let t = time * SHAKE_FREQUENCY + seed;
+        ((t * 1.1).sin() + (t * 2.3 + seed).sin() * 0.5) / 1.5
+    };
+    Vec2::new(wobble(0.), wobble(10.)) * MAX_SHAKE_OFFSET * shake
+}
+
+/// shake and zoom the cameras, after they've followed the players
+fn apply_camera_effects(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    effects: Res<CameraEffects>,
+    mut query: Query<(&mut Transform, &mut OrthographicProjection), With<FollowCamera>>,
+) {
+    let offset = shake_offset(
+        effects.trauma,
+        settings.screen_shake,
+        time.elapsed_seconds(),
+    );
+    // zooming in, so the view never shows more than the follow logic allowed for
+    let zoom = 1. - effects.punch * settings.screen_shake.clamp(0., 1.);
+    for (mut transform, mut projection) in query.iter_mut() {
+        transform.translation.x += offset.x;
+        transform.translation.y += offset.y;
+        projection.scale *= zoom;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shake() {
+        // still without trauma, or with shake turned off
+        assert_eq!(shake_offset(0., 1., 3.), Vec2::ZERO);
+        assert_eq!(shake_offset(1., 0., 3.), Vec2::ZERO);
+
+        for i in 0..100 {
+            let time = i as f32 * 0.01;
+            let big = shake_offset(1., 1., time);
+            let small = shake_offset(0.5, 1., time);
+            assert!(big.abs().max_element() <= MAX_SHAKE_OFFSET);
+            // a quarter as much with half the trauma
+            assert!((small * 4. - big).length() < 0.001);
+        }
+        // and actually moving
+        assert_ne!(shake_offset(1., 1., 0.1), shake_offset(1., 1., 0.2));
+    }
+}
@@ -1,5 +1,10 @@
 use bevy::prelude::*;
 
+use crate::{
+    constants::{PLAYER_HEALTH, PLAYER_RUN_SPEED},
+    map::PickupKind,
+};
+
 #[derive(Component, Copy, Clone, PartialEq, Eq)]
 pub enum Direction {
     Left,
@@ -26,6 +31,7 @@ pub struct Player {
 
     pub min_jump_speed: f32,
     pub jump_speed: f32,
+    pub climb_speed: f32,
 }
 
 impl Default for Player {
@@ -36,26 +42,129 @@ impl Default for Player {
 
             min_jump_speed: 200.0,
             jump_speed: 410.0,
+            climb_speed: 120.0,
         }
     }
 }
 
-#[derive(Component)]
+#[derive(Component, PartialEq, Eq)]
 pub enum PlayerState {
     Stand,
     Walk,
     Jump,
+    Climb,
+    Dead,
 }
 
 #[derive(Component)]
 pub struct PlayerReadyAttack(pub bool);
 
+#[derive(Component)]
+pub struct Health {
+    pub current: i32,
+    pub max: i32,
+}
+
+impl Health {
+    pub fn new(max: i32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn damage(&mut self, amount: i32) {
+        self.current = (self.current - amount).max(0);
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.max;
+    }
+
+    pub fn heal(&mut self, amount: i32) {
+        self.current = (self.current + amount).min(self.max);
+    }
+}
+
+/// what a player has picked up
+#[derive(Component, Default)]
+pub struct Inventory {
+    pub keys: u32,
+    pub coins: u32,
+}
+
+/// the keys a player is moved with, each action takes any of its keys
+#[derive(Component, Clone)]
+pub struct Controls {
+    pub left: Vec<KeyCode>,
+    pub right: Vec<KeyCode>,
+    pub up: Vec<KeyCode>,
+    pub down: Vec<KeyCode>,
+    pub jump: Vec<KeyCode>,
+    pub attack: Vec<KeyCode>,
+}
+
+impl Default for Controls {
+    /// a single player gets both the arrows and WASD
+    fn default() -> Self {
+        Self {
+            left: vec![KeyCode::Left, KeyCode::A],
+            right: vec![KeyCode::Right, KeyCode::D],
+            up: vec![KeyCode::Up, KeyCode::W],
+            down: vec![KeyCode::Down, KeyCode::S],
+            jump: vec![KeyCode::Space],
+            attack: vec![KeyCode::Return],
+        }
+    }
+}
+
+impl Controls {
+    /// WASD, space to jump and F to attack, for the first of two players on a keyboard
+    pub fn left_hand() -> Self {
+        Self {
+            left: vec![KeyCode::A],
+            right: vec![KeyCode::D],
+            up: vec![KeyCode::W],
+            down: vec![KeyCode::S],
+            jump: vec![KeyCode::Space],
+            attack: vec![KeyCode::F],
+        }
+    }
+
+    /// the arrows, right shift to jump and return to attack, for the second player
+    pub fn right_hand() -> Self {
+        Self {
+            left: vec![KeyCode::Left],
+            right: vec![KeyCode::Right],
+            up: vec![KeyCode::Up],
+            down: vec![KeyCode::Down],
+            jump: vec![KeyCode::RShift],
+            attack: vec![KeyCode::Return],
+        }
+    }
+}
+
+/// present while the attack animation plays, which fires and then removes it with its events
+#[derive(Component)]
+pub struct Attacking;
+
+/// present while the death animation plays, the entity respawns once the timer finishes
+#[derive(Component)]
+pub struct Dying {
+    pub timer: Timer,
+}
+
 #[derive(Bundle)]
 pub struct PlayerBundle {
     player: Player,
     state: PlayerState,
     ready_attack: PlayerReadyAttack,
     speed: Speed,
+    health: Health,
+    inventory: Inventory,
+    respawn: RespawnPoint,
+    controls: Controls,
 }
 
 impl Default for PlayerBundle {
@@ -64,7 +173,11 @@ impl Default for PlayerBundle {
             player: Player::default(),
             state: PlayerState::Stand,
             ready_attack: PlayerReadyAttack(true),
-            speed: Speed::new(240.0, 0.0),
+            speed: Speed::new(PLAYER_RUN_SPEED, 0.0),
+            health: Health::new(PLAYER_HEALTH),
+            inventory: Inventory::default(),
+            respawn: RespawnPoint::default(),
+            controls: Controls::default(),
         }
     }
 }
@@ -84,6 +197,9 @@ pub struct RigidBody {
     pub at_ceiling: bool,
     pub at_right_tile: bool,
     pub at_left_tile: bool,
+
+    /// pass through one-way colliders (platforms, ladder tops), e.g. while climbing
+    pub ignore_platforms: bool,
 }
 
 impl Default for RigidBody {
@@ -99,6 +215,7 @@ impl Default for RigidBody {
             on_platform: false,
             at_right_tile: false,
             at_left_tile: false,
+            ignore_platforms: false,
         }
     }
 }
@@ -141,6 +258,11 @@ impl Collider {
         self.pos.x = pos.x;
         self.pos.y = pos.y;
     }
+
+    /// can only be collided with from above
+    pub fn is_one_way(&self) -> bool {
+        self.top && !self.bottom
+    }
 }
 impl Default for Collider {
     fn default() -> Self {
@@ -176,3 +298,63 @@ pub struct Projectile {
 
 #[derive(Component)]
 pub struct Tile;
+
+/// relative position in the map of a tile sprite
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TilePos {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// spawned from one of the map's entity markers, replaced when the map's entities change
+#[derive(Component)]
+pub struct MapEntity;
+
+/// a rigid body that falls, for bodies which aren't moved by their own system
+#[derive(Component)]
+pub struct Gravity;
+
+#[derive(Component)]
+pub struct Crate;
+
+#[derive(Component)]
+pub struct Enemy;
+
+#[derive(Component)]
+pub struct Pickup {
+    pub kind: PickupKind,
+}
+
+/// a respawn point at a relative tile position, activated by a player touching it
+#[derive(Component)]
+pub struct Checkpoint {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// the last checkpoint a player activated, as the level's path and the checkpoint's tile
+#[derive(Component, Default)]
+pub struct RespawnPoint(pub Option<(String, i32, i32)>);
+
+/// damage is ignored until the timer finishes
+#[derive(Component)]
+pub struct Invulnerable {
+    pub timer: Timer,
+}
+
+/// a light source, see `lighting`
+#[derive(Component, Clone, Copy, Debug)]
+pub struct PointLight2d {
+    pub color: Color,
+    /// distance at which the light has faded out completely
+    pub radius: f32,
+    /// brightness at the center, 1 is fully lit
+    pub intensity: f32,
+}
+
+/// a light which fades out and despawns, like a muzzle flash
+#[derive(Component)]
+pub struct Flash {
+    pub timer: Timer,
+    pub intensity: f32,
+}
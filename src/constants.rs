@@ -2,6 +2,46 @@ pub const GRAVITY: f32 = -1030.0;
 
 pub const MAX_FALLING_SPEED: f32 = -900.0;
 
+// landing faster than this shakes the camera
+pub const LANDING_SHAKE_SPEED: f32 = 500.0;
+
 pub const SPRITE_SCALE: f32 = 2.0;
 
 pub const PLATFORM_THRESHOLD: f32 = 2.;
+
+pub const PLAYER_HEALTH: i32 = 100;
+
+// how fast players run, in pixels per second
+pub const PLAYER_RUN_SPEED: f32 = 240.0;
+
+pub const LAVA_DAMAGE: i32 = 100;
+
+// seconds between dying and respawning
+pub const DEATH_DURATION: f32 = 1.0;
+
+// seconds a player can't be hurt for after respawning
+pub const RESPAWN_INVULNERABILITY: f32 = 2.0;
+
+// seconds of invulnerability after surviving a hit from a hazard
+pub const HAZARD_INVULNERABILITY: f32 = 0.75;
+
+// seconds between the camera effects of standing in a hazard
+pub const HAZARD_EFFECT_INTERVAL: f32 = 0.5;
+
+// hits a breakable tile takes, unless it has a `health` property
+pub const BREAKABLE_HEALTH: i32 = 3;
+
+// how far the explosion of an explosive tile reaches, unless it has an `explosion_radius`
+pub const EXPLOSION_RADIUS: f32 = 40.;
+
+// damage done by an explosion, enough to destroy most breakable tiles
+pub const EXPLOSION_DAMAGE: i32 = 10;
+
+// health given back by a health pickup
+pub const HEALTH_PICKUP: i32 = 25;
+
+// size in pixels of a cell of the light map
+pub const LIGHT_CELL_SIZE: f32 = 8.;
+
+// seconds a muzzle flash lasts
+pub const FLASH_DURATION: f32 = 0.12;
@@ -0,0 +1,543 @@
+use bevy::prelude::*;
+
+use crate::{
+    camera::FollowCamera,
+    map::{Campaign, Level, LevelLoaded, Map, MapSpawner, NavAction, NavGraph, NavStep, TileType},
+};
+
+// how many edits can be undone
+const HISTORY_SIZE: usize = 100;
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Editor>()
+            .init_resource::<EditorHistory>()
+            .add_startup_system(setup_editor)
+            .add_system(editor_toggle)
+            .add_system(editor_brush)
+            .add_system(editor_paint.after(editor_toggle))
+            .add_system(editor_history.after(editor_paint))
+            .add_system(editor_resize.after(editor_history))
+            .add_system(editor_save)
+            .add_system(editor_level_loaded)
+            .add_system(editor_cursor.after(editor_paint))
+            .add_system(editor_path.after(editor_paint))
+            .add_system(editor_markers.after(editor_resize));
+    }
+}
+
+/// what gets painted with the mouse
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Brush {
+    Tile(TileType),
+    /// a starting position
+    Spawn,
+}
+
+#[derive(Resource)]
+pub struct Editor {
+    pub enabled: bool,
+    pub brush: Brush,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            brush: Brush::Tile(TileType::Solid),
+        }
+    }
+}
+
+/// snapshots of the map before each edit
+#[derive(Resource, Default)]
+pub struct EditorHistory {
+    undo: Vec<Map>,
+    redo: Vec<Map>,
+}
+
+impl EditorHistory {
+    /// remember the map as it was before an edit
+    pub fn record(&mut self, map: &Map) {
+        self.undo.push(map.clone());
+        if self.undo.len() > HISTORY_SIZE {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// returns the map to go back to, if there is one
+    pub fn undo(&mut self, current: &Map) -> Option<Map> {
+        let map = self.undo.pop()?;
+        self.redo.push(current.clone());
+        Some(map)
+    }
+
+    pub fn redo(&mut self, current: &Map) -> Option<Map> {
+        let map = self.redo.pop()?;
+        self.undo.push(current.clone());
+        Some(map)
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+/// highlights the tile under the mouse
+#[derive(Component)]
+pub struct EditorCursor;
+
+/// shows a starting position while editing
+#[derive(Component)]
+pub struct EditorMarker;
+
+/// shows a step of the path to the cursor, see `editor_path`
+#[derive(Component)]
+pub struct EditorPathStep;
+
+/// the point in the world under the cursor, through whichever camera's viewport it's in
+fn cursor_world_position(
+    windows: &Windows,
+    camera_query: &Query<(&Camera, &GlobalTransform), With<FollowCamera>>,
+) -> Option<Vec2> {
+    let window = windows.get_primary()?;
+    let cursor = window.cursor_position()?;
+    // viewports are measured from the top left, the cursor from the bottom left
+    let from_top = Vec2::new(cursor.x, window.height() - cursor.y);
+    camera_query.iter().find_map(|(camera, transform)| {
+        let (min, max) = camera.logical_viewport_rect()?;
+        if from_top.cmplt(min).any() || from_top.cmpge(max).any() {
+            return None;
+        }
+        camera
+            .viewport_to_world(transform, Vec2::new(from_top.x - min.x, max.y - from_top.y))
+            .map(|ray| ray.origin.truncate())
+    })
+}
+
+fn ctrl_pressed(kb: &Input<KeyCode>) -> bool {
+    kb.any_pressed([KeyCode::LControl, KeyCode::RControl])
+}
+
+fn setup_editor(mut commands: Commands, map: Res<Map>) {
+    let size = map.tile_size as f32;
+    commands.spawn((
+        EditorCursor,
+        SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::new(size, size)),
+                color: Color::rgba(1., 1., 1., 0.3),
+                ..default()
+            },
+            transform: Transform::from_xyz(0., 0., 50.),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        },
+    ));
+}
+
+fn editor_toggle(kb: Res<Input<KeyCode>>, mut editor: ResMut<Editor>) {
+    if kb.just_pressed(KeyCode::F1) {
+        editor.enabled = !editor.enabled;
+        println!(
+            "Editor {}",
+            if editor.enabled {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+    }
+}
+
+fn editor_brush(kb: Res<Input<KeyCode>>, mut editor: ResMut<Editor>) {
+    if !editor.enabled {
+        return;
+    }
+    let brush = if kb.just_pressed(KeyCode::Key1) {
+        Brush::Tile(TileType::Empty)
+    } else if kb.just_pressed(KeyCode::Key2) {
+        Brush::Tile(TileType::Solid)
+    } else if kb.just_pressed(KeyCode::Key3) {
+        Brush::Tile(TileType::Platform)
+    } else if kb.just_pressed(KeyCode::Key4) {
+        Brush::Tile(TileType::Ladder)
+    } else if kb.just_pressed(KeyCode::Key5) {
+        Brush::Tile(TileType::Lava)
+    } else if kb.just_pressed(KeyCode::Key6) {
+        Brush::Tile(TileType::Exit)
+    } else if kb.just_pressed(KeyCode::Key7) {
+        Brush::Spawn
+    } else if kb.just_pressed(KeyCode::Key8) {
+        Brush::Tile(TileType::Breakable)
+    } else if kb.just_pressed(KeyCode::Key9) {
+        Brush::Tile(TileType::Door)
+    } else if kb.just_pressed(KeyCode::Key0) {
+        Brush::Tile(TileType::Switch)
+    } else {
+        return;
+    };
+    editor.brush = brush;
+    println!("Editor brush {:?}", brush);
+}
+
+/// left click paints the brush, right click erases
+#[allow(clippy::too_many_arguments)]
+fn editor_paint(
+    mouse: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<FollowCamera>>,
+    editor: Res<Editor>,
+    mut history: ResMut<EditorHistory>,
+    mut map: ResMut<Map>,
+    mut spawner: MapSpawner,
+    mut recorded: Local<bool>,
+) {
+    if !editor.enabled {
+        return;
+    }
+    // a whole stroke is undone at once
+    let stroke_start = mouse.any_just_pressed([MouseButton::Left, MouseButton::Right]);
+    if stroke_start {
+        *recorded = false;
+    }
+
+    let erase = mouse.pressed(MouseButton::Right);
+    if !erase && !mouse.pressed(MouseButton::Left) {
+        return;
+    }
+    let (x, y) = match cursor_world_position(&windows, &camera_query) {
+        Some(point) => map.tile_coords(point),
+        None => return,
+    };
+    if !map.in_bounds(x, y) {
+        return;
+    }
+
+    // most frames of a stroke paint over what's already there, the map is only copied for
+    // actual edits
+    let new_map = match editor.brush {
+        Brush::Tile(tile) => {
+            let tile = if erase { TileType::Empty } else { tile };
+            if map.tile(x, y) == tile {
+                return;
+            }
+            let mut new_map = map.clone();
+            new_map.set_tile(x, y, tile);
+            // properties belong to the tile that was painted over
+            new_map.properties.remove(&(x, y));
+            new_map
+        }
+        Brush::Spawn => {
+            if !stroke_start {
+                return;
+            }
+            let pos = map.tile_position(x, y);
+            let existing = map
+                .starting_positions
+                .iter()
+                .position(|p| map.tile_coords(*p) == (x, y));
+            match (erase, existing) {
+                (true, Some(idx)) => {
+                    // players need somewhere to spawn
+                    if map.starting_positions.len() == 1 {
+                        println!("Not removing the last starting position");
+                        return;
+                    }
+                    let mut new_map = map.clone();
+                    new_map.starting_positions.remove(idx);
+                    new_map
+                }
+                (false, None) => {
+                    // spawns are saved in place of a tile
+                    let mut new_map = map.clone();
+                    new_map.set_tile(x, y, TileType::Empty);
+                    new_map.starting_positions.push(pos);
+                    new_map
+                }
+                _ => return,
+            }
+        }
+    };
+
+    if !*recorded {
+        history.record(&map);
+        *recorded = true;
+    }
+    spawner.replace(&mut map, new_map);
+}
+
+/// ctrl+z to undo, ctrl+y or ctrl+shift+z to redo
+fn editor_history(
+    kb: Res<Input<KeyCode>>,
+    editor: Res<Editor>,
+    mut history: ResMut<EditorHistory>,
+    mut map: ResMut<Map>,
+    mut spawner: MapSpawner,
+) {
+    if !editor.enabled || !ctrl_pressed(&kb) {
+        return;
+    }
+    let shift = kb.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    let new_map = if kb.just_pressed(KeyCode::Y) || (shift && kb.just_pressed(KeyCode::Z)) {
+        history.redo(&map)
+    } else if kb.just_pressed(KeyCode::Z) {
+        history.undo(&map)
+    } else {
+        return;
+    };
+    if let Some(new_map) = new_map {
+        spawner.replace(&mut map, new_map);
+    }
+}
+
+/// [ and ] change the width of the map, - and = the height
+fn editor_resize(
+    kb: Res<Input<KeyCode>>,
+    editor: Res<Editor>,
+    mut history: ResMut<EditorHistory>,
+    mut map: ResMut<Map>,
+    mut spawner: MapSpawner,
+) {
+    if !editor.enabled {
+        return;
+    }
+    let (width, height) = if kb.just_pressed(KeyCode::LBracket) {
+        (map.width - 1, map.height)
+    } else if kb.just_pressed(KeyCode::RBracket) {
+        (map.width + 1, map.height)
+    } else if kb.just_pressed(KeyCode::Minus) {
+        (map.width, map.height - 1)
+    } else if kb.just_pressed(KeyCode::Equals) {
+        (map.width, map.height + 1)
+    } else {
+        return;
+    };
+    if width < 1 || height < 1 {
+        return;
+    }
+
+    let new_map = map.resized(width, height);
+    if new_map.starting_positions.is_empty() {
+        println!("Not resizing, it would remove every starting position");
+        return;
+    }
+    println!("Resizing map to {}x{}", width, height);
+    history.record(&map);
+    spawner.replace(&mut map, new_map);
+}
+
+/// ctrl+s writes the map back to the current level's file
+fn editor_save(
+    kb: Res<Input<KeyCode>>,
+    editor: Res<Editor>,
+    campaign: Res<Campaign>,
+    map: Res<Map>,
+) {
+    if !editor.enabled || !ctrl_pressed(&kb) || !kb.just_pressed(KeyCode::S) {
+        return;
+    }
+    let path = campaign.current_path();
+    // keeps the name, background and anything else that isn't edited here
+    let mut level = match Level::try_load(path) {
+        Ok(level) => level,
+        Err(e) => {
+            println!("Not saving level: {}", e);
+            return;
+        }
+    };
+    level.tiles = map.to_prefab();
+    level.properties = map.properties.clone();
+    match level.save(path) {
+        Ok(()) => println!("Saved {}", path),
+        Err(e) => println!("Not saving level: {}", e),
+    }
+}
+
+/// edits to a previous level can't be undone
+fn editor_level_loaded(
+    mut ev_loaded: EventReader<LevelLoaded>,
+    mut history: ResMut<EditorHistory>,
+) {
+    if ev_loaded.iter().last().is_some() {
+        history.clear();
+    }
+}
+
+/// the tile under the cursor while editing
+fn cursor_tile(
+    windows: &Windows,
+    camera_query: &Query<(&Camera, &GlobalTransform), With<FollowCamera>>,
+    editor: &Editor,
+    map: &Map,
+) -> Option<(i32, i32)> {
+    cursor_world_position(windows, camera_query)
+        .map(|point| map.tile_coords(point))
+        .filter(|(x, y)| editor.enabled && map.in_bounds(*x, *y))
+}
+
+/// the spawn brush is red where a character couldn't stand
+fn editor_cursor(
+    windows: Res<Windows>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<FollowCamera>>,
+    editor: Res<Editor>,
+    map: Res<Map>,
+    graph: Res<NavGraph>,
+    mut query: Query<(&mut Transform, &mut Visibility, &mut Sprite), With<EditorCursor>>,
+) {
+    let coords = cursor_tile(&windows, &camera_query, &editor, &map);
+
+    for (mut transform, mut visibility, mut sprite) in query.iter_mut() {
+        visibility.is_visible = coords.is_some();
+        if let Some((x, y)) = coords {
+            let pos = map.tile_position(x, y);
+            transform.translation.x = pos.x;
+            transform.translation.y = pos.y;
+            sprite.color = match editor.brush {
+                Brush::Spawn if graph.contains(x, y) => Color::rgba(0.2, 0.4, 1., 0.5),
+                Brush::Spawn => Color::rgba(1., 0.2, 0.2, 0.5),
+                _ => Color::rgba(1., 1., 1., 0.3),
+            };
+        }
+    }
+}
+
+fn step_color(step: &NavStep) -> Color {
+    match step.action {
+        NavAction::Walk => Color::rgba(0.2, 0.4, 1., 0.8),
+        NavAction::Fall | NavAction::Drop => Color::rgba(0.2, 0.8, 1., 0.8),
+        NavAction::Climb => Color::rgba(0.4, 1., 0.4, 0.8),
+        NavAction::Jump { .. } => Color::rgba(1., 0.8, 0.2, 0.8),
+    }
+}
+
+/// the first starting position and the tile under the cursor
+type PathEnds = ((i32, i32), (i32, i32));
+
+/// with the spawn brush, show how a player would get from the first starting position to the
+/// cursor, coloured by how each tile is reached
+#[allow(clippy::too_many_arguments)]
+fn editor_path(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<FollowCamera>>,
+    editor: Res<Editor>,
+    map: Res<Map>,
+    graph: Res<NavGraph>,
+    mut shown: Local<Option<PathEnds>>,
+    query: Query<Entity, With<EditorPathStep>>,
+) {
+    let ends = match (
+        editor.brush,
+        cursor_tile(&windows, &camera_query, &editor, &map),
+        map.starting_positions.first(),
+    ) {
+        (Brush::Spawn, Some(to), Some(start)) => Some((map.tile_coords(*start), to)),
+        _ => None,
+    };
+    if ends == *shown && !graph.is_changed() {
+        return;
+    }
+    *shown = ends;
+
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let path = match ends.and_then(|(from, to)| graph.find_path(from, to)) {
+        Some(path) => path,
+        None => return,
+    };
+    let size = map.tile_size as f32 / 4.;
+    for step in path.iter() {
+        let pos = map.tile_position(step.to.0, step.to.1);
+        commands.spawn((
+            EditorPathStep,
+            SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(size, size)),
+                    color: step_color(step),
+                    ..default()
+                },
+                transform: Transform::from_xyz(pos.x, pos.y, 50.),
+                ..default()
+            },
+        ));
+    }
+}
+
+/// show the starting positions while editing
+fn editor_markers(
+    mut commands: Commands,
+    editor: Res<Editor>,
+    map: Res<Map>,
+    query: Query<Entity, With<EditorMarker>>,
+) {
+    if !editor.is_changed() && !map.is_changed() {
+        return;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !editor.enabled {
+        return;
+    }
+
+    let size = map.tile_size as f32 / 2.;
+    for pos in map.starting_positions.iter() {
+        commands.spawn((
+            EditorMarker,
+            SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(size, size)),
+                    color: Color::rgba(0.2, 0.4, 1., 0.8),
+                    ..default()
+                },
+                transform: Transform::from_xyz(pos.x, pos.y, 50.),
+                ..default()
+            },
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> Map {
+        Map::from_ascii(
+            "
+            -X--E
+            -##-=
+            ^^||^
+            ",
+        )
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut history = EditorHistory::default();
+        let mut map = map();
+        let original = map.clone();
+
+        history.record(&map);
+        map.set_tile(0, 0, TileType::Solid);
+        let edited = map.clone();
+
+        let map = history.undo(&map).unwrap();
+        assert_eq!(map.chunks, original.chunks);
+        assert!(history.undo(&map).is_none());
+
+        let map = history.redo(&map).unwrap();
+        assert_eq!(map.chunks, edited.chunks);
+        assert!(history.redo(&map).is_none());
+
+        // a new edit forgets what could be redone
+        history.undo(&map).unwrap();
+        history.record(&original);
+        assert!(history.redo(&original).is_none());
+    }
+}
@@ -0,0 +1,244 @@
+use bevy::prelude::*;
+
+use crate::{
+    components::{
+        Checkpoint, Collider, Crate, Enemy, Gravity, Health, Inventory, MapEntity, Pickup, Player,
+        PointLight2d, RespawnPoint, RigidBody,
+    },
+    constants::{GRAVITY, HEALTH_PICKUP, MAX_FALLING_SPEED},
+    map::{Campaign, EnemyKind, EntitySpawn, LevelLoaded, Map, PickupKind, SpawnKind},
+    resources::Checkpoints,
+};
+
+pub struct EntitiesPlugin;
+
+impl Plugin for EntitiesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Checkpoints>()
+            .add_system(spawn_map_entities)
+            .add_system(apply_gravity)
+            .add_system(collect_pickups)
+            .add_system(activate_checkpoints);
+    }
+}
+
+/// spawn the entities placed in the map, again whenever a level is loaded or they change
+#[allow(clippy::too_many_arguments)]
+fn spawn_map_entities(
+    mut commands: Commands,
+    map: Res<Map>,
+    campaign: Res<Campaign>,
+    checkpoints: Res<Checkpoints>,
+    asset_server: Res<AssetServer>,
+    mut ev_loaded: EventReader<LevelLoaded>,
+    mut spawned: Local<Option<Vec<EntitySpawn>>>,
+    query: Query<Entity, With<MapEntity>>,
+) {
+    let level_loaded = ev_loaded.iter().last().is_some();
+    if !level_loaded && spawned.as_ref() == Some(&map.spawns) {
+        return;
+    }
+
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for spawn in map.spawns.iter() {
+        let activated = checkpoints.activated.contains(&(
+            campaign.current_path().to_string(),
+            spawn.x,
+            spawn.y,
+        ));
+        spawn_entity(&mut commands, &map, &asset_server, spawn, activated);
+    }
+    *spawned = Some(map.spawns.clone());
+}
+
+fn spawn_entity(
+    commands: &mut Commands,
+    map: &Map,
+    asset_server: &AssetServer,
+    spawn: &EntitySpawn,
+    activated: bool,
+) {
+    let pos = map.tile_position(spawn.x, spawn.y);
+    let transform = Transform::from_xyz(pos.x, pos.y, 5.);
+    // bodies start falling rather than standing on whatever they were placed on
+    let body = || RigidBody {
+        on_ground: false,
+        ..RigidBody::from_transform(transform)
+    };
+
+    match spawn.kind {
+        SpawnKind::Crate => {
+            commands.spawn((
+                MapEntity,
+                Crate,
+                Gravity,
+                body(),
+                Collider::from_position(transform.translation, Vec2::new(12., 12.)),
+                SpriteBundle {
+                    texture: asset_server.load("crate.png"),
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::new(24., 24.)),
+                        ..default()
+                    },
+                    transform,
+                    ..default()
+                },
+            ));
+        }
+        SpawnKind::Enemy(kind) => {
+            // no art for enemies yet, so they're tinted knights
+            let color = match kind {
+                EnemyKind::Slime => Color::rgb(0.4, 1., 0.4),
+                EnemyKind::Skeleton => Color::rgb(0.6, 0.6, 0.6),
+            };
+            commands.spawn((
+                MapEntity,
+                Enemy,
+                Gravity,
+                body(),
+                Collider::from_position(transform.translation, Vec2::new(8., 10.)),
+                SpriteBundle {
+                    texture: asset_server.load("anim/idle/knight_m_idle_anim_f0.png"),
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::new(16., 20.)),
+                        flip_x: true,
+                        color,
+                        ..default()
+                    },
+                    transform,
+                    ..default()
+                },
+            ));
+        }
+        SpawnKind::Pickup(kind) => {
+            let color = match kind {
+                PickupKind::Health => Color::rgb(1., 0.2, 0.2),
+                PickupKind::Coin => Color::rgb(1., 0.85, 0.1),
+                PickupKind::Key => Color::rgb(1., 0.55, 0.),
+            };
+            commands.spawn((
+                MapEntity,
+                Pickup { kind },
+                SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::new(10., 10.)),
+                        color,
+                        ..default()
+                    },
+                    transform,
+                    ..default()
+                },
+            ));
+        }
+        SpawnKind::Checkpoint => {
+            commands.spawn((
+                MapEntity,
+                Checkpoint {
+                    x: spawn.x,
+                    y: spawn.y,
+                },
+                SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::new(4., map.tile_size as f32)),
+                        color: checkpoint_color(activated),
+                        ..default()
+                    },
+                    transform,
+                    ..default()
+                },
+            ));
+        }
+        SpawnKind::Torch => {
+            commands.spawn((
+                MapEntity,
+                PointLight2d {
+                    color: Color::rgb(1., 0.6, 0.25),
+                    radius: 160.,
+                    intensity: 0.9,
+                },
+                SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::new(6., 12.)),
+                        color: Color::rgb(1., 0.7, 0.2),
+                        ..default()
+                    },
+                    transform,
+                    ..default()
+                },
+            ));
+        }
+    }
+}
+
+fn checkpoint_color(activated: bool) -> Color {
+    if activated {
+        Color::rgb(0.4, 1., 0.5)
+    } else {
+        Color::rgb(0.3, 0.5, 1.)
+    }
+}
+
+/// bodies resting on the ground keep being pulled down, so they fall once it's gone
+fn apply_gravity(time: Res<Time>, mut query: Query<&mut RigidBody, With<Gravity>>) {
+    for mut body in query.iter_mut() {
+        body.speed.y = (body.speed.y + GRAVITY * time.delta_seconds()).max(MAX_FALLING_SPEED);
+    }
+}
+
+/// players pick up whatever they touch
+fn collect_pickups(
+    mut commands: Commands,
+    mut players: Query<(&Collider, &mut Inventory, &mut Health), With<Player>>,
+    pickups: Query<(Entity, &Transform, &Pickup)>,
+) {
+    for (collider, mut inventory, mut health) in players.iter_mut() {
+        for (entity, transform, pickup) in pickups.iter() {
+            let distance = (transform.translation.truncate() - collider.pos).abs();
+            if distance.x > collider.half.x + 5. || distance.y > collider.half.y + 5. {
+                continue;
+            }
+            match pickup.kind {
+                PickupKind::Health => health.heal(HEALTH_PICKUP),
+                PickupKind::Coin => inventory.coins += 1,
+                PickupKind::Key => inventory.keys += 1,
+            }
+            println!("Picked up {:?}", pickup.kind);
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// players touching a checkpoint respawn there from now on
+fn activate_checkpoints(
+    map: Res<Map>,
+    campaign: Res<Campaign>,
+    mut checkpoints: ResMut<Checkpoints>,
+    mut players: Query<(&Collider, &mut RespawnPoint), With<Player>>,
+    mut query: Query<(&Checkpoint, &Transform, &mut Sprite)>,
+) {
+    for (collider, mut respawn) in players.iter_mut() {
+        for (checkpoint, transform, mut sprite) in query.iter_mut() {
+            let distance = (transform.translation.truncate() - collider.pos).abs();
+            // checkpoints are a thin post, one tile high
+            if distance.x > collider.half.x + 2.
+                || distance.y > collider.half.y + map.tile_size as f32 / 2.
+            {
+                continue;
+            }
+            let point = (
+                campaign.current_path().to_string(),
+                checkpoint.x,
+                checkpoint.y,
+            );
+            if respawn.0.as_ref() == Some(&point) {
+                continue;
+            }
+            println!("Activated checkpoint at {}, {}", checkpoint.x, checkpoint.y);
+            sprite.color = checkpoint_color(true);
+            checkpoints.activated.insert(point.clone());
+            respawn.0 = Some(point);
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        view::RenderLayers,
+    },
+};
+
+use crate::{
+    camera::CameraLayer,
+    camera_effects::MAX_SHAKE_OFFSET,
+    components::{Flash, PointLight2d},
+    constants::LIGHT_CELL_SIZE,
+    editor::Editor,
+    map::Map,
+};
+
+// light given off by every lava tile
+const LAVA_LIGHT: PointLight2d = PointLight2d {
+    color: Color::rgb(1., 0.45, 0.1),
+    radius: 64.,
+    intensity: 0.6,
+};
+
+/// darkens the scene, except around light sources
+///
+/// the light map is worked out on the CPU, one cell per `LIGHT_CELL_SIZE` pixels of the view,
+/// and drawn over everything as a single sprite per camera. that keeps it to plain sprite
+/// rendering, so it still works with software renderers.
+pub struct LightingPlugin;
+
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_light_maps)
+            .add_system(fade_flashes)
+            .add_system(update_light_map.after(fade_flashes));
+    }
+}
+
+/// the sprite a camera's light map is drawn with
+#[derive(Component)]
+struct LightOverlay {
+    camera: Entity,
+}
+
+/// a light map for each camera, only drawn by that camera
+fn spawn_light_maps(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    camera_query: Query<(Entity, &CameraLayer)>,
+    overlay_query: Query<(Entity, &LightOverlay)>,
+) {
+    for (entity, overlay) in overlay_query.iter() {
+        if !camera_query.contains(overlay.camera) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    for (camera, layer) in camera_query.iter() {
+        if overlay_query
+            .iter()
+            .any(|(_, overlay)| overlay.camera == camera)
+        {
+            continue;
+        }
+        let image = Image::new_fill(
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[0, 0, 0, 0],
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        commands.spawn((
+            LightOverlay { camera },
+            SpriteBundle {
+                texture: images.add(image),
+                // above the map and entities
+                transform: Transform::from_xyz(0., 0., 50.),
+                ..default()
+            },
+            RenderLayers::layer(layer.0),
+        ));
+    }
+}
+
+/// how much a cell is lit by each light, and with what color
+#[derive(Clone, Copy, Default)]
+struct CellLight {
+    amount: f32,
+    color: Vec3,
+}
+
+/// whether nothing solid is in the way between a light and a point
+///
+/// the tile the point is in doesn't block it, so walls facing a light are lit.
+fn reaches(map: &Map, from: Vec2, to: Vec2) -> bool {
+    let start = map.tile_coords(from);
+    let end = map.tile_coords(to);
+    let steps = ((to - from).length() / (map.tile_size as f32 / 4.)).ceil() as i32;
+    (1..steps).all(|i| {
+        let (x, y) = map.tile_coords(from.lerp(to, i as f32 / steps as f32));
+        (x, y) == start || (x, y) == end || !map.is_obstacle(x, y)
+    })
+}
+
+/// rgba pixels of the light map, with `origin` as the bottom left corner of the bottom left cell
+///
+/// rows go from the top down, like an image. the pixels are what's drawn over the scene: black,
+/// and transparent where it's lit, tinted by the color of the lights.
+fn light_map(
+    map: &Map,
+    origin: Vec2,
+    size: (usize, usize),
+    lights: &[(Vec2, PointLight2d)],
+) -> Vec<u8> {
+    let (width, height) = size;
+    let mut cells = vec![CellLight::default(); width * height];
+    for (pos, light) in lights.iter() {
+        let min = ((*pos - light.radius - origin) / LIGHT_CELL_SIZE).floor();
+        let max = ((*pos + light.radius - origin) / LIGHT_CELL_SIZE).ceil();
+        for y in (min.y.max(0.) as usize)..(max.y.max(0.) as usize).min(height) {
+            for x in (min.x.max(0.) as usize)..(max.x.max(0.) as usize).min(width) {
+                let cell = origin + (Vec2::new(x as f32, y as f32) + 0.5) * LIGHT_CELL_SIZE;
+                let distance = cell.distance(*pos);
+                if distance >= light.radius || !reaches(map, *pos, cell) {
+                    continue;
+                }
+                let amount = light.intensity * (1. - distance / light.radius).powi(2);
+                let color = Vec3::new(light.color.r(), light.color.g(), light.color.b());
+                let cell = &mut cells[y * width + x];
+                cell.amount += amount;
+                cell.color += color * amount;
+            }
+        }
+    }
+
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for y in (0..height).rev() {
+        for cell in cells[y * width..(y + 1) * width].iter() {
+            let lit = (map.ambient + cell.amount).min(1.);
+            // a glow of the lights' color, strongest half way out
+            let tint = cell.color / cell.amount.max(0.001) * cell.amount.min(1.) * 0.5;
+            pixels.extend_from_slice(&[
+                (tint.x.min(1.) * 255.) as u8,
+                (tint.y.min(1.) * 255.) as u8,
+                (tint.z.min(1.) * 255.) as u8,
+                ((1. - lit) * 255.) as u8,
+            ]);
+        }
+    }
+    pixels
+}
+
+/// redraw each camera's light map over its view
+#[allow(clippy::type_complexity)]
+fn update_light_map(
+    map: Res<Map>,
+    editor: Res<Editor>,
+    mut images: ResMut<Assets<Image>>,
+    camera_query: Query<(&GlobalTransform, &OrthographicProjection), With<CameraLayer>>,
+    light_query: Query<(&GlobalTransform, &PointLight2d)>,
+    mut overlay_query: Query<(
+        &LightOverlay,
+        &Handle<Image>,
+        &mut Sprite,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+) {
+    let lights: Vec<(Vec2, PointLight2d)> = light_query
+        .iter()
+        .map(|(transform, light)| (transform.translation().truncate(), *light))
+        .collect();
+
+    for (overlay, handle, mut sprite, mut transform, mut visibility) in overlay_query.iter_mut() {
+        // the editor needs to see everything
+        visibility.is_visible = map.ambient < 1. && !editor.enabled;
+        if !visibility.is_visible {
+            continue;
+        }
+        let (center, half) = match camera_query.get(overlay.camera) {
+            Ok((transform, projection)) => (
+                transform.translation().truncate(),
+                Vec2::new(
+                    projection.right - projection.left,
+                    projection.top - projection.bottom,
+                ) * projection.scale
+                    / 2.,
+            ),
+            Err(_) => continue,
+        };
+        // shaking moves the camera after this is drawn, so cover where it might move to
+        let half = half + MAX_SHAKE_OFFSET;
+
+        // snapped to whole cells so the light map doesn't shimmer when the camera moves
+        let origin = ((center - half) / LIGHT_CELL_SIZE).floor() * LIGHT_CELL_SIZE;
+        let cells = ((center + half - origin) / LIGHT_CELL_SIZE).ceil();
+        let size = (cells.x as usize, cells.y as usize);
+
+        let mut lights = lights.clone();
+        let (min_x, min_y) = map.tile_coords(origin - LAVA_LIGHT.radius);
+        let (max_x, max_y) = map.tile_coords(center + half + LAVA_LIGHT.radius);
+        for y in min_y.max(0)..=max_y.min(map.height - 1) {
+            for x in min_x.max(0)..=max_x.min(map.width - 1) {
+                if map.is_hazard(x, y) {
+                    lights.push((map.tile_position(x, y), LAVA_LIGHT));
+                }
+            }
+        }
+
+        let image = match images.get_mut(handle) {
+            Some(image) => image,
+            None => continue,
+        };
+        let extent = Extent3d {
+            width: size.0 as u32,
+            height: size.1 as u32,
+            depth_or_array_layers: 1,
+        };
+        if image.texture_descriptor.size != extent {
+            image.resize(extent);
+        }
+        image.data = light_map(&map, origin, size, &lights);
+
+        let world_size = cells * LIGHT_CELL_SIZE;
+        sprite.custom_size = Some(world_size);
+        let middle = origin + world_size / 2.;
+        transform.translation.x = middle.x;
+        transform.translation.y = middle.y;
+    }
+}
+
+/// flashes fade out, then go away
+fn fade_flashes(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Flash, &mut PointLight2d)>,
+) {
+    for (entity, mut flash, mut light) in query.iter_mut() {
+        flash.timer.tick(time.delta());
+        light.intensity = flash.intensity * flash.timer.percent_left();
+        if flash.timer.finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alpha(pixels: &[u8], size: (usize, usize), x: usize, y: usize) -> u8 {
+        // rows are top down
+        pixels[((size.1 - 1 - y) * size.0 + x) * 4 + 3]
+    }
+
+    #[test]
+    fn walls_cast_shadows() {
+        let mut map = Map::from_ascii(
+            "
+            ------
+            --#---
+            ------
+            ",
+        );
+        map.ambient = 0.;
+        let light = PointLight2d {
+            color: Color::WHITE,
+            radius: 200.,
+            intensity: 1.,
+        };
+        // one cell per quarter tile, lit from the left of the wall
+        let origin = map.tile_position(0, 0) - map.tile_size as f32 / 2.;
+        let size = (24, 12);
+        let lights = [(map.tile_position(0, 1), light)];
+        let pixels = light_map(&map, origin, size, &lights);
+        assert_eq!(pixels.len(), 24 * 12 * 4);
+
+        // next to the light, the wall itself, and behind the wall
+        assert!(alpha(&pixels, size, 2, 5) < 40);
+        assert!(alpha(&pixels, size, 8, 5) < 255);
+        assert_eq!(alpha(&pixels, size, 17, 5), 255);
+        // around the wall
+        assert!(alpha(&pixels, size, 12, 11) < 255);
+    }
+
+    #[test]
+    fn ambient() {
+        let mut map = Map::from_ascii("---");
+        map.ambient = 0.5;
+        let origin = map.tile_position(0, 0) - map.tile_size as f32 / 2.;
+        let pixels = light_map(&map, origin, (12, 4), &[]);
+        assert!(pixels.chunks(4).all(|p| p == [0, 0, 0, 127]));
+    }
+}
@@ -1,35 +1,69 @@
+mod animation;
+mod assets;
+mod camera;
+mod camera_effects;
 mod components;
 mod constants;
+mod editor;
+mod entities;
+mod lighting;
 mod map;
+mod minimap;
 mod physics;
 mod player;
 mod resources;
+mod settings;
 
-use bevy::{prelude::*, window::PresentMode};
+use animation::AnimationPlugin;
+use bevy::{
+    prelude::*,
+    window::{PresentMode, WindowMode},
+};
+use camera::{CameraPlugin, VIRTUAL_SIZE};
+use camera_effects::CameraEffectsPlugin;
+use editor::EditorPlugin;
+use entities::EntitiesPlugin;
+use lighting::LightingPlugin;
 use map::MapPlugin;
+use minimap::MinimapPlugin;
 use physics::{DebugPhysicsPlugin, PhysicsPlugin};
 use player::PlayerPlugin;
-use resources::{CharacterAnimation, Materials, WinSize};
+use resources::Materials;
+use settings::{Settings, DEFAULT_SETTINGS};
 
 fn main() {
+    let settings = Settings::load(DEFAULT_SETTINGS);
     App::new()
         .insert_resource(ClearColor(Color::rgb(0.04, 0.04, 0.04)))
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             window: WindowDescriptor {
                 title: "Hackweek 2021".to_string(),
-                width: 1400.0,
-                height: 768.0,
+                width: VIRTUAL_SIZE.x,
+                height: VIRTUAL_SIZE.y,
                 present_mode: PresentMode::Immediate,
-                resizable: false,
+                resizable: true,
+                mode: if settings.fullscreen {
+                    WindowMode::BorderlessFullscreen
+                } else {
+                    WindowMode::Windowed
+                },
                 position: WindowPosition::Centered,
                 ..default()
             },
             ..default()
         }))
+        .insert_resource(settings)
         .add_plugin(PhysicsPlugin)
         .add_plugin(DebugPhysicsPlugin)
         .add_plugin(PlayerPlugin)
+        .add_plugin(AnimationPlugin)
+        .add_plugin(CameraPlugin)
+        .add_plugin(CameraEffectsPlugin)
         .add_plugin(MapPlugin)
+        .add_plugin(EditorPlugin)
+        .add_plugin(EntitiesPlugin)
+        .add_plugin(LightingPlugin)
+        .add_plugin(MinimapPlugin)
         .add_startup_system(setup)
         .run();
 }
@@ -37,56 +71,8 @@ fn main() {
 fn setup(
     mut commands: Commands,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    mut windows: ResMut<Windows>,
     asset_server: Res<AssetServer>,
 ) {
-    // camera
-    commands.spawn(Camera2dBundle::default());
-
-    commands.insert_resource(CharacterAnimation {
-        idle_f0: materials.add(
-            asset_server
-                .load("anim/idle/knight_m_idle_anim_f0.png")
-                .into(),
-        ),
-        idle_f1: materials.add(
-            asset_server
-                .load("anim/idle/knight_m_idle_anim_f1.png")
-                .into(),
-        ),
-        idle_f2: materials.add(
-            asset_server
-                .load("anim/idle/knight_m_idle_anim_f2.png")
-                .into(),
-        ),
-        idle_f3: materials.add(
-            asset_server
-                .load("anim/idle/knight_m_idle_anim_f3.png")
-                .into(),
-        ),
-
-        run_f0: materials.add(
-            asset_server
-                .load("anim/run/knight_m_run_anim_f0.png")
-                .into(),
-        ),
-        run_f1: materials.add(
-            asset_server
-                .load("anim/run/knight_m_run_anim_f1.png")
-                .into(),
-        ),
-        run_f2: materials.add(
-            asset_server
-                .load("anim/run/knight_m_run_anim_f2.png")
-                .into(),
-        ),
-        run_f3: materials.add(
-            asset_server
-                .load("anim/run/knight_m_run_anim_f3.png")
-                .into(),
-        ),
-    });
-
     // create the main resources
     commands.insert_resource(Materials {
         red: materials.add(Color::rgb(1., 0., 0.).into()),
@@ -94,30 +80,5 @@ fn setup(
         blue: materials.add(Color::rgb(0., 0., 1.).into()),
 
         projectile: materials.add(asset_server.load("bullet.png").into()),
-
-        tile_wall_left: materials.add(asset_server.load("wall_left.png").into()),
-        tile_wall_middle: materials.add(asset_server.load("wall_mid.png").into()),
-        tile_wall_right: materials.add(asset_server.load("wall_right.png").into()),
-        tile_edge: materials.add(asset_server.load("edge.png").into()),
-        tile_ladder: materials.add(asset_server.load("ladder.png").into()),
-
-        tile_lava_01: materials.add(asset_server.load("lava_01.png").into()),
-        tile_lava_02: materials.add(asset_server.load("lava_02.png").into()),
-        tile_lava_03: materials.add(asset_server.load("lava_03.png").into()),
-        tile_lava_04: materials.add(asset_server.load("lava_04.png").into()),
-        tile_lava_05: materials.add(asset_server.load("lava_05.png").into()),
-        tile_lava_06: materials.add(asset_server.load("lava_06.png").into()),
-        tile_lava_07: materials.add(asset_server.load("lava_07.png").into()),
-        tile_lava_08: materials.add(asset_server.load("lava_08.png").into()),
-        tile_lava_09: materials.add(asset_server.load("lava_09.png").into()),
-        tile_lava_10: materials.add(asset_server.load("lava_10.png").into()),
-        tile_lava_11: materials.add(asset_server.load("lava_11.png").into()),
-        tile_lava_12: materials.add(asset_server.load("lava_12.png").into()),
-    });
-
-    let window = windows.get_primary_mut().unwrap();
-    commands.insert_resource(WinSize {
-        w: window.width(),
-        h: window.height(),
     });
 }
@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use super::tileset::Tileset;
+
+/// a tile whose texture is driven by one of the tileset animations
+#[derive(Component)]
+pub struct AnimatedTile {
+    pub animation: String,
+}
+
+/// all animated tiles share the same clock (time since startup), so every tile using
+/// an animation shows the same frame
+pub fn animate_tiles(
+    time: Res<Time>,
+    tileset: Res<Tileset>,
+    asset_server: Res<AssetServer>,
+    mut frames: Local<HashMap<String, Vec<Handle<Image>>>>,
+    mut query: Query<(&AnimatedTile, &mut Handle<Image>)>,
+) {
+    let elapsed = time.elapsed_seconds();
+    for (tile, mut texture) in query.iter_mut() {
+        let animation = match tileset.animations.get(&tile.animation) {
+            Some(animation) => animation,
+            None => continue,
+        };
+        let handles = frames.entry(tile.animation.clone()).or_insert_with(|| {
+            animation
+                .frames
+                .iter()
+                .map(|f| asset_server.load(f.as_str()))
+                .collect()
+        });
+        if let Some(frame) = handles.get(animation.frame_at(elapsed)) {
+            if *texture != *frame {
+                *texture = frame.clone();
+            }
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use serde::Deserialize;
+
+use super::generator::{Map, TileType};
+
+/// a neighbouring tile, relative to the tile being drawn
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub enum Neighbour {
+    N,
+    NE,
+    E,
+    SE,
+    S,
+    SW,
+    W,
+    NW,
+}
+
+impl Neighbour {
+    pub const ALL: [Neighbour; 8] = [
+        Neighbour::N,
+        Neighbour::NE,
+        Neighbour::E,
+        Neighbour::SE,
+        Neighbour::S,
+        Neighbour::SW,
+        Neighbour::W,
+        Neighbour::NW,
+    ];
+
+    /// bit used for this neighbour in a mask (clockwise from north)
+    pub fn bit(&self) -> u8 {
+        1 << (*self as u8)
+    }
+
+    /// tile offset of this neighbour (y grows upwards, same as `Map`)
+    pub fn offset(&self) -> (i32, i32) {
+        match self {
+            Neighbour::N => (0, 1),
+            Neighbour::NE => (1, 1),
+            Neighbour::E => (1, 0),
+            Neighbour::SE => (1, -1),
+            Neighbour::S => (0, -1),
+            Neighbour::SW => (-1, -1),
+            Neighbour::W => (-1, 0),
+            Neighbour::NW => (-1, 1),
+        }
+    }
+
+    pub fn is_diagonal(&self) -> bool {
+        matches!(
+            self,
+            Neighbour::NE | Neighbour::SE | Neighbour::SW | Neighbour::NW
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Connectivity {
+    #[default]
+    Four,
+    Eight,
+}
+
+/// compute the neighbour mask for the tile at x/y
+///
+/// a bit is set when the neighbouring tile is one of `connects`. tiles outside of the map
+/// are `Solid` (see `Map::tile`), so walls connect to the edges of the map.
+pub fn neighbour_mask(
+    map: &Map,
+    x: i32,
+    y: i32,
+    connects: &[TileType],
+    connectivity: Connectivity,
+) -> u8 {
+    let mut mask = 0;
+    for neighbour in Neighbour::ALL.iter() {
+        if connectivity == Connectivity::Four && neighbour.is_diagonal() {
+            continue;
+        }
+        let (dx, dy) = neighbour.offset();
+        if connects.contains(&map.tile(x + dx, y + dy)) {
+            mask |= neighbour.bit();
+        }
+    }
+    mask
+}
+
+pub fn mask_of(neighbours: &[Neighbour]) -> u8 {
+    neighbours.iter().fold(0, |mask, n| mask | n.bit())
+}
+
+/// a rule matches when every `require`d neighbour is connected and no `forbid`den one is
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Rule<T> {
+    #[serde(default)]
+    pub require: Vec<Neighbour>,
+    #[serde(default)]
+    pub forbid: Vec<Neighbour>,
+    pub sprite: T,
+}
+
+impl<T> Rule<T> {
+    pub fn matches(&self, mask: u8) -> bool {
+        let require = mask_of(&self.require);
+        let forbid = mask_of(&self.forbid);
+        mask & require == require && mask & forbid == 0
+    }
+}
+
+/// returns the first rule matching the mask
+pub fn select<T>(rules: &[Rule<T>], mask: u8) -> Option<&Rule<T>> {
+    rules.iter().find(|r| r.matches(mask))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> Map {
+        Map::from_prefab((
+            "
+            ------
+            -###--
+            -#-#=-
+            ^^^^^^
+            ^^^^^^
+            ",
+            6,
+            5,
+        ))
+    }
+
+    #[test]
+    fn four_neighbour_mask() {
+        let map = map();
+        let solid = [TileType::Solid];
+        // top left of the block: wall to the east and south
+        assert_eq!(
+            neighbour_mask(&map, 1, 3, &solid, Connectivity::Four),
+            mask_of(&[Neighbour::E, Neighbour::S])
+        );
+        // top middle: walls either side, air below
+        assert_eq!(
+            neighbour_mask(&map, 2, 3, &solid, Connectivity::Four),
+            mask_of(&[Neighbour::E, Neighbour::W])
+        );
+        // outside the map counts as solid
+        assert_eq!(
+            neighbour_mask(&map, 0, 4, &solid, Connectivity::Four),
+            mask_of(&[Neighbour::N, Neighbour::W])
+        );
+    }
+
+    #[test]
+    fn eight_neighbour_mask() {
+        let map = map();
+        let solid = [TileType::Solid];
+        assert_eq!(
+            neighbour_mask(&map, 2, 2, &solid, Connectivity::Eight),
+            mask_of(&[
+                Neighbour::N,
+                Neighbour::NE,
+                Neighbour::E,
+                Neighbour::W,
+                Neighbour::NW
+            ])
+        );
+    }
+
+    #[test]
+    fn lava_surface_and_body() {
+        let map = map();
+        let lava = [TileType::Lava];
+        let rules = vec![
+            Rule {
+                require: vec![Neighbour::N],
+                forbid: vec![],
+                sprite: "body",
+            },
+            Rule {
+                require: vec![],
+                forbid: vec![],
+                sprite: "surface",
+            },
+        ];
+        let mask = neighbour_mask(&map, 2, 1, &lava, Connectivity::Four);
+        assert_eq!(select(&rules, mask).unwrap().sprite, "surface");
+        let mask = neighbour_mask(&map, 2, 0, &lava, Connectivity::Four);
+        assert_eq!(select(&rules, mask).unwrap().sprite, "body");
+    }
+
+    #[test]
+    fn rule_forbid() {
+        let rule = Rule {
+            require: vec![Neighbour::E],
+            forbid: vec![Neighbour::W],
+            sprite: (),
+        };
+        assert!(rule.matches(mask_of(&[Neighbour::E])));
+        assert!(rule.matches(mask_of(&[Neighbour::E, Neighbour::N])));
+        assert!(!rule.matches(mask_of(&[Neighbour::E, Neighbour::W])));
+        assert!(!rule.matches(0));
+    }
+}
@@ -0,0 +1,223 @@
+use bevy::{prelude::*, render::view::RenderLayers};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::CameraLayer;
+
+use super::Map;
+
+/// an image drawn behind the map, which scrolls slower than it to look further away
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct BackgroundLayer {
+    pub texture: String,
+    /// size to draw the texture at
+    pub size: (f32, f32),
+    /// how much of the camera's movement the layer follows: 0 moves with the map, 1 stays put
+    /// on screen
+    #[serde(default)]
+    pub parallax: (f32, f32),
+    /// repeat the texture horizontally, and vertically, to fill the view
+    #[serde(default)]
+    pub repeat: (bool, bool),
+    /// where the layer is when the camera is at 0/0
+    #[serde(default)]
+    pub offset: (f32, f32),
+    /// tint: red, green, blue, alpha
+    #[serde(default)]
+    pub color: Option<(f32, f32, f32, f32)>,
+}
+
+impl BackgroundLayer {
+    fn size(&self) -> Vec2 {
+        Vec2::new(self.size.0, self.size.1)
+    }
+
+    /// number of copies of the texture needed to cover a view, across and down
+    fn copies(&self, view_half: Vec2) -> (i32, i32) {
+        let count = |repeat: bool, view: f32, size: f32| {
+            if repeat {
+                (view / size).ceil() as i32 + 1
+            } else {
+                1
+            }
+        };
+        (
+            count(self.repeat.0, view_half.x * 2., self.size.0),
+            count(self.repeat.1, view_half.y * 2., self.size.1),
+        )
+    }
+
+    /// center of the bottom left copy, for a camera at `center`
+    fn origin(&self, center: Vec2, view_half: Vec2) -> Vec2 {
+        let size = self.size();
+        let mut origin = center * Vec2::new(self.parallax.0, self.parallax.1)
+            + Vec2::new(self.offset.0, self.offset.1);
+
+        // move repeating layers by whole copies to the first one overlapping the view
+        let min = center - view_half;
+        if self.repeat.0 {
+            origin.x += ((min.x - origin.x + size.x / 2.) / size.x).floor() * size.x;
+        }
+        if self.repeat.1 {
+            origin.y += ((min.y - origin.y + size.y / 2.) / size.y).floor() * size.y;
+        }
+        origin
+    }
+}
+
+/// parent of the sprites drawing one of the map's background layers, for one camera
+#[derive(Component)]
+pub struct Background {
+    camera: Entity,
+    index: usize,
+    copies: (i32, i32),
+}
+
+/// spawn the map's background layers for each camera, again whenever they change
+pub fn spawn_background(
+    mut commands: Commands,
+    map: Res<Map>,
+    mut spawned: Local<Option<Vec<BackgroundLayer>>>,
+    camera_query: Query<(Entity, &CameraLayer)>,
+    query: Query<(Entity, &Background)>,
+) {
+    let changed = spawned.as_ref() != Some(&map.background);
+    for (entity, background) in query.iter() {
+        if changed || !camera_query.contains(background.camera) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for (camera, layer) in camera_query.iter() {
+        if !changed && query.iter().any(|(_, b)| b.camera == camera) {
+            continue;
+        }
+        for index in 0..map.background.len() {
+            // the sprites are spawned by `scroll_background`, once it knows how many are needed
+            commands.spawn((
+                Background {
+                    camera,
+                    index,
+                    copies: (0, 0),
+                },
+                SpatialBundle::default(),
+                RenderLayers::layer(layer.0),
+            ));
+        }
+    }
+    *spawned = Some(map.background.clone());
+}
+
+/// keep the background layers filling each camera's view as it moves
+pub fn scroll_background(
+    mut commands: Commands,
+    map: Res<Map>,
+    asset_server: Res<AssetServer>,
+    camera_query: Query<(&GlobalTransform, &OrthographicProjection, &CameraLayer)>,
+    mut query: Query<(Entity, &mut Background, &mut Transform)>,
+) {
+    for (entity, mut background, mut transform) in query.iter_mut() {
+        let layer = match map.background.get(background.index) {
+            Some(layer) => layer,
+            None => continue,
+        };
+        let (center, view_half, render_layer) = match camera_query.get(background.camera) {
+            Ok((transform, projection, render_layer)) => (
+                transform.translation().truncate(),
+                Vec2::new(
+                    projection.right - projection.left,
+                    projection.top - projection.bottom,
+                ) * projection.scale
+                    / 2.,
+                render_layer.0,
+            ),
+            Err(_) => continue,
+        };
+
+        let copies = layer.copies(view_half);
+        if copies != background.copies {
+            background.copies = copies;
+            let size = layer.size();
+            let color = layer
+                .color
+                .map(|(r, g, b, a)| Color::rgba(r, g, b, a))
+                .unwrap_or(Color::WHITE);
+            commands.entity(entity).despawn_descendants();
+            commands.entity(entity).with_children(|parent| {
+                for y in 0..copies.1 {
+                    for x in 0..copies.0 {
+                        parent.spawn((
+                            SpriteBundle {
+                                texture: asset_server.load(&layer.texture),
+                                sprite: Sprite {
+                                    custom_size: Some(size),
+                                    color,
+                                    ..default()
+                                },
+                                transform: Transform::from_xyz(
+                                    x as f32 * size.x,
+                                    y as f32 * size.y,
+                                    0.,
+                                ),
+                                ..default()
+                            },
+                            // only drawn by the camera it was placed for
+                            RenderLayers::layer(render_layer),
+                        ));
+                    }
+                }
+            });
+        }
+
+        // behind the tiles, and in the order they're listed
+        let origin = layer.origin(center, view_half);
+        transform.translation = Vec3::new(origin.x, origin.y, background.index as f32 * 0.01);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(parallax: (f32, f32), repeat: (bool, bool)) -> BackgroundLayer {
+        BackgroundLayer {
+            texture: "wall_mid.png".into(),
+            size: (100., 50.),
+            parallax,
+            repeat,
+            offset: (10., 0.),
+            color: None,
+        }
+    }
+
+    #[test]
+    fn copies() {
+        let view_half = Vec2::new(200., 100.);
+        assert_eq!(layer((0., 0.), (false, false)).copies(view_half), (1, 1));
+        assert_eq!(layer((0., 0.), (true, false)).copies(view_half), (5, 1));
+        assert_eq!(layer((0., 0.), (true, true)).copies(view_half), (5, 5));
+    }
+
+    #[test]
+    fn parallax() {
+        let view_half = Vec2::new(200., 100.);
+        let center = Vec2::new(400., 200.);
+        // moves with the map
+        let fixed = layer((0., 0.), (false, false));
+        assert_eq!(fixed.origin(center, view_half), Vec2::new(10., 0.));
+        // stays on screen
+        let far = layer((1., 1.), (false, false));
+        assert_eq!(far.origin(center, view_half), Vec2::new(410., 200.));
+
+        let half = layer((0.5, 0.5), (true, true));
+        let origin = half.origin(center, view_half);
+        assert_eq!(origin, Vec2::new(210., 100.));
+        // the first copy covers the bottom left of the view, and the last the top right
+        let (across, down) = half.copies(view_half);
+        let min = center - view_half;
+        let max = center + view_half;
+        assert!(origin.x - 50. <= min.x && origin.x + 50. > min.x);
+        assert!(origin.y - 25. <= min.y && origin.y + 25. > min.y);
+        assert!(origin.x + (across as f32 - 0.5) * 100. >= max.x);
+        assert!(origin.y + (down as f32 - 0.5) * 50. >= max.y);
+    }
+}
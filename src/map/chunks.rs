@@ -0,0 +1,227 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::camera::FollowCamera;
+
+use super::{colliders::TileRect, generator::TileType, plugin::spawn_chunk, tileset::Tileset, Map};
+
+/// width and height of a chunk, in tiles
+pub const CHUNK_SIZE: i32 = 16;
+
+const CHUNK_TILES: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// the tiles of a square of `CHUNK_SIZE` tiles, see `Map::chunks`
+///
+/// chunks on the right and top edges of a map which aren't whole keep the tiles past the edge
+/// empty.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chunk {
+    tiles: [TileType; CHUNK_TILES],
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self {
+            tiles: [TileType::Empty; CHUNK_TILES],
+        }
+    }
+}
+
+impl Chunk {
+    /// the tile at a position relative to the chunk's bottom left tile
+    pub fn tile(&self, x: i32, y: i32) -> TileType {
+        self.tiles[(y * CHUNK_SIZE + x) as usize]
+    }
+
+    pub fn set_tile(&mut self, x: i32, y: i32, tile: TileType) {
+        self.tiles[(y * CHUNK_SIZE + x) as usize] = tile;
+    }
+}
+
+/// the position of a chunk, in chunks from the bottom left of the map
+///
+/// spawned tile sprites are tagged with their chunk, so they can be despawned together.
+/// colliders are spawned for the whole map, see `spawn_colliders`.
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl ChunkPos {
+    /// the chunk containing a tile
+    pub fn of_tile(x: i32, y: i32) -> Self {
+        Self {
+            x: x.div_euclid(CHUNK_SIZE),
+            y: y.div_euclid(CHUNK_SIZE),
+        }
+    }
+
+    /// the tiles in this chunk, clipped to the map
+    pub fn area(&self, map: &Map) -> TileRect {
+        TileRect {
+            x0: self.x * CHUNK_SIZE,
+            y0: self.y * CHUNK_SIZE,
+            x1: ((self.x + 1) * CHUNK_SIZE).min(map.width) - 1,
+            y1: ((self.y + 1) * CHUNK_SIZE).min(map.height) - 1,
+        }
+    }
+}
+
+impl Map {
+    /// how many chunks a map of this size is stored in
+    pub fn chunk_count(width: i32, height: i32) -> usize {
+        let chunks = |tiles: i32| (tiles.max(0) + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        (chunks(width) * chunks(height)) as usize
+    }
+
+    /// index of a chunk in `Map::chunks`
+    pub fn chunk_index(&self, chunk: ChunkPos) -> usize {
+        let chunks_wide = (self.width + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        (chunk.y * chunks_wide + chunk.x) as usize
+    }
+
+    /// the chunk at an index in `Map::chunks`
+    pub fn chunk_at(&self, index: usize) -> ChunkPos {
+        let chunks_wide = (self.width + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        ChunkPos {
+            x: index as i32 % chunks_wide,
+            y: index as i32 / chunks_wide,
+        }
+    }
+
+    pub fn has_chunk(&self, chunk: ChunkPos) -> bool {
+        self.in_bounds(chunk.x * CHUNK_SIZE, chunk.y * CHUNK_SIZE)
+    }
+
+    /// the chunks of the map which overlap a box
+    pub fn chunks_overlapping(&self, min: Vec2, max: Vec2) -> Vec<ChunkPos> {
+        let (min_x, min_y) = self.tile_coords(min);
+        let (max_x, max_y) = self.tile_coords(max);
+        let min = ChunkPos::of_tile(min_x.max(0), min_y.max(0));
+        let max = ChunkPos::of_tile(max_x.min(self.width - 1), max_y.min(self.height - 1));
+
+        let mut chunks = Vec::new();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                chunks.push(ChunkPos { x, y });
+            }
+        }
+        chunks
+    }
+}
+
+/// chunks with spawned tile sprites
+#[derive(Resource, Default)]
+pub struct LoadedChunks {
+    pub chunks: HashSet<ChunkPos>,
+}
+
+/// spawn the chunks near a camera and despawn the ones that are far away
+///
+/// chunks are loaded within half a chunk of the view, and only unloaded once they're a whole
+/// chunk away so moving back and forth on a boundary doesn't keep respawning them.
+#[allow(clippy::too_many_arguments)]
+pub fn stream_chunks(
+    mut commands: Commands,
+    map: Res<Map>,
+    tileset: Res<Tileset>,
+    asset_server: Res<AssetServer>,
+    mut loaded: ResMut<LoadedChunks>,
+    camera_query: Query<(&GlobalTransform, &OrthographicProjection), With<FollowCamera>>,
+    chunk_query: Query<(Entity, &ChunkPos)>,
+) {
+    let chunk_size = (CHUNK_SIZE * map.tile_size) as f32;
+    let mut wanted = HashSet::new();
+    let mut kept = HashSet::new();
+    for (transform, projection) in camera_query.iter() {
+        let center = transform.translation().truncate();
+        let half = Vec2::new(
+            projection.right - projection.left,
+            projection.top - projection.bottom,
+        ) * projection.scale
+            / 2.;
+
+        let load = half + chunk_size / 2.;
+        wanted.extend(map.chunks_overlapping(center - load, center + load));
+        let keep = half + chunk_size;
+        kept.extend(map.chunks_overlapping(center - keep, center + keep));
+    }
+
+    let unload: HashSet<ChunkPos> = loaded
+        .chunks
+        .iter()
+        .filter(|c| !kept.contains(c))
+        .copied()
+        .collect();
+    if !unload.is_empty() {
+        for (entity, chunk) in chunk_query.iter() {
+            if unload.contains(chunk) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        loaded.chunks.retain(|c| !unload.contains(c));
+    }
+
+    for chunk in wanted {
+        if loaded.chunks.insert(chunk) {
+            spawn_chunk(&mut commands, &map, &tileset, &asset_server, chunk);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn of_tile() {
+        assert_eq!(ChunkPos::of_tile(0, 0), ChunkPos { x: 0, y: 0 });
+        assert_eq!(ChunkPos::of_tile(15, 16), ChunkPos { x: 0, y: 1 });
+        assert_eq!(ChunkPos::of_tile(-1, 33), ChunkPos { x: -1, y: 2 });
+    }
+
+    #[test]
+    fn chunks() {
+        let map = Map::new(40, 16);
+        assert!(map.has_chunk(ChunkPos { x: 2, y: 0 }));
+        assert!(!map.has_chunk(ChunkPos { x: 3, y: 0 }));
+        assert!(!map.has_chunk(ChunkPos { x: 0, y: 1 }));
+
+        // the last chunk is clipped to the map
+        assert_eq!(
+            ChunkPos { x: 2, y: 0 }.area(&map),
+            TileRect {
+                x0: 32,
+                y0: 0,
+                x1: 39,
+                y1: 15
+            }
+        );
+    }
+
+    #[test]
+    fn chunks_overlapping() {
+        let mut map = Map::new(64, 32);
+        map.position = Vec3::ZERO;
+
+        let chunks = map.chunks_overlapping(Vec2::new(0., 0.), Vec2::new(100., 100.));
+        assert_eq!(chunks, vec![ChunkPos { x: 0, y: 0 }]);
+
+        let chunks = map.chunks_overlapping(Vec2::new(400., 0.), Vec2::new(600., 600.));
+        assert_eq!(
+            chunks,
+            vec![
+                ChunkPos { x: 0, y: 0 },
+                ChunkPos { x: 1, y: 0 },
+                ChunkPos { x: 0, y: 1 },
+                ChunkPos { x: 1, y: 1 },
+            ]
+        );
+
+        // clipped to the map
+        let chunks = map.chunks_overlapping(Vec2::splat(-5000.), Vec2::splat(5000.));
+        assert_eq!(chunks.len(), 8);
+    }
+}
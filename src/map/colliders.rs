@@ -0,0 +1,297 @@
+use bevy::prelude::*;
+
+use crate::components::Collider;
+
+use super::generator::Map;
+
+/// a rectangle of tiles, inclusive
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TileRect {
+    pub x0: i32,
+    pub y0: i32,
+    pub x1: i32,
+    pub y1: i32,
+}
+
+impl TileRect {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x0 && x <= self.x1 && y >= self.y0 && y <= self.y1
+    }
+}
+
+/// greedily merge the tiles within `area` matching `include` into rectangles
+///
+/// runs are grown to the right first, then upwards for as long as the whole run is
+/// matched. with `vertical` false only horizontal runs are merged.
+pub fn merge_tiles(
+    map: &Map,
+    area: TileRect,
+    include: impl Fn(i32, i32) -> bool,
+    vertical: bool,
+) -> Vec<TileRect> {
+    // only the part of the map that's being merged is tracked
+    let area = TileRect {
+        x0: area.x0.max(0),
+        y0: area.y0.max(0),
+        x1: area.x1.min(map.width - 1),
+        y1: area.y1.min(map.height - 1),
+    };
+    if area.x1 < area.x0 || area.y1 < area.y0 {
+        return Vec::new();
+    }
+    let width = area.x1 - area.x0 + 1;
+    let index = |x: i32, y: i32| ((y - area.y0) * width + x - area.x0) as usize;
+    let mut used = vec![false; index(area.x1, area.y1) + 1];
+    let available = |used: &Vec<bool>, x: i32, y: i32| -> bool {
+        area.contains(x, y) && !used[index(x, y)] && include(x, y)
+    };
+
+    let mut rects = Vec::new();
+    for y in area.y0..=area.y1 {
+        for x in area.x0..=area.x1 {
+            if !available(&used, x, y) {
+                continue;
+            }
+
+            let mut x1 = x;
+            while available(&used, x1 + 1, y) {
+                x1 += 1;
+            }
+
+            let mut y1 = y;
+            while vertical && (x..=x1).all(|cx| available(&used, cx, y1 + 1)) {
+                y1 += 1;
+            }
+
+            for ry in y..=y1 {
+                for rx in x..=x1 {
+                    used[index(rx, ry)] = true;
+                }
+            }
+            rects.push(TileRect {
+                x0: x,
+                y0: y,
+                x1,
+                y1,
+            });
+        }
+    }
+    rects
+}
+
+/// the colliders for the tiles within `area`, with neighbouring tiles merged into as few
+/// colliders as possible so bodies don't catch on the seams between tiles
+pub fn bake_colliders(map: &Map, area: TileRect) -> Vec<Collider> {
+    let tile_size = map.tile_size as f32;
+    let half_tile = tile_size / 2.;
+    let center = |rect: &TileRect| -> Vec2 {
+        (map.tile_position(rect.x0, rect.y0) + map.tile_position(rect.x1, rect.y1)) / 2.
+    };
+    let half = |rect: &TileRect| -> Vec2 {
+        Vec2::new(
+            (rect.x1 - rect.x0 + 1) as f32 * half_tile,
+            (rect.y1 - rect.y0 + 1) as f32 * half_tile,
+        )
+    };
+    let one_way = |pos: Vec2, half_x: f32| Collider {
+        pos,
+        half: Vec2::new(half_x, 0.5),
+        top: true,
+        left: true,
+        right: true,
+        bottom: false,
+    };
+
+    let mut colliders = Vec::new();
+
+    for rect in merge_tiles(map, area, |x, y| map.is_obstacle(x, y), true) {
+        colliders.push(Collider::new(center(&rect), half(&rect)));
+    }
+
+    for rect in merge_tiles(map, area, |x, y| map.is_platform(x, y), false) {
+        colliders.push(one_way(center(&rect), half(&rect).x));
+    }
+
+    // the top of a ladder can be stood on
+    let ladder_top = |x: i32, y: i32| map.is_ladder(x, y) && !map.is_ladder(x, y + 1);
+    for rect in merge_tiles(map, area, ladder_top, false) {
+        let pos = center(&rect) + Vec2::new(0., half_tile - 0.5);
+        colliders.push(one_way(pos, half(&rect).x));
+    }
+
+    colliders
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{
+        chunks::{ChunkPos, CHUNK_SIZE},
+        test_maps::edge_cases,
+    };
+
+    fn map(prefab: (&str, i32, i32)) -> Map {
+        let mut map = Map::from_prefab(prefab);
+        map.position = Vec3::ZERO;
+        map
+    }
+
+    fn ascii_map(prefab: &str) -> Map {
+        let mut map = Map::from_ascii(prefab);
+        map.position = Vec3::ZERO;
+        map
+    }
+
+    fn bake(map: &Map) -> Vec<Collider> {
+        let area = TileRect {
+            x0: 0,
+            y0: 0,
+            x1: map.width - 1,
+            y1: map.height - 1,
+        };
+        bake_colliders(map, area)
+    }
+
+    fn index(map: &Map, x: i32, y: i32) -> usize {
+        (y * map.width + x) as usize
+    }
+
+    /// how many colliders of each kind cover every tile
+    fn coverage(map: &Map, colliders: &[Collider]) -> (Vec<i32>, Vec<i32>) {
+        let mut solid = vec![0; (map.width * map.height) as usize];
+        let mut one_way = vec![0; (map.width * map.height) as usize];
+        for collider in colliders {
+            for (x, y) in map.tiles_overlapping(collider.pos, collider.half) {
+                assert!(x >= 0 && x < map.width && y >= 0 && y < map.height);
+                if collider.is_one_way() {
+                    one_way[index(map, x, y)] += 1;
+                } else {
+                    solid[index(map, x, y)] += 1;
+                }
+            }
+        }
+        (solid, one_way)
+    }
+
+    fn assert_exact_coverage(map: &Map, colliders: &[Collider]) {
+        let (solid, one_way) = coverage(map, colliders);
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let idx = index(map, x, y);
+                let expected_solid = map.is_obstacle(x, y) as i32;
+                let expected_one_way = (map.is_platform(x, y)
+                    || (map.is_ladder(x, y) && !map.is_ladder(x, y + 1)))
+                    as i32;
+                assert_eq!(solid[idx], expected_solid, "solid at {}, {}", x, y);
+                assert_eq!(one_way[idx], expected_one_way, "one way at {}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn merges_runs_and_rectangles() {
+        let map = map((
+            "
+            ------
+            -###--
+            -###==
+            ^^^^^^
+            ",
+            6,
+            4,
+        ));
+        let colliders = bake(&map);
+        assert_eq!(colliders.len(), 2);
+
+        let block = colliders.iter().find(|c| !c.is_one_way()).unwrap();
+        assert_eq!(block.half, Vec2::new(48., 32.));
+        assert_eq!(block.pos, Vec2::new(64., 48.));
+
+        let platform = colliders.iter().find(|c| c.is_one_way()).unwrap();
+        assert_eq!(platform.half, Vec2::new(32., 0.5));
+
+        assert_exact_coverage(&map, &colliders);
+    }
+
+    #[test]
+    fn does_not_merge_platform_rows() {
+        let map = map((
+            "
+            ===
+            ===
+            ",
+            3,
+            2,
+        ));
+        assert_eq!(bake(&map).len(), 2);
+        assert_exact_coverage(&map, &bake(&map));
+    }
+
+    #[test]
+    fn ladder_tops() {
+        let map = map((
+            "
+            |-|
+            |-|
+            ###
+            ",
+            3,
+            3,
+        ));
+        let colliders = bake(&map);
+        assert_eq!(colliders.len(), 3);
+        assert_exact_coverage(&map, &colliders);
+    }
+
+    #[test]
+    fn areas_outside_the_map() {
+        let map = map((
+            "
+            #-=
+            ###
+            ",
+            3,
+            2,
+        ));
+        let around = TileRect {
+            x0: -5,
+            y0: -5,
+            x1: 10,
+            y1: 10,
+        };
+        assert_eq!(bake_colliders(&map, around).len(), bake(&map).len());
+        let outside = TileRect {
+            x0: 3,
+            y0: 0,
+            x1: 10,
+            y1: 1,
+        };
+        assert!(bake_colliders(&map, outside).is_empty());
+    }
+
+    const CHARS: [char; 5] = ['-', '#', '=', '|', '^'];
+
+    #[test]
+    fn covers_exactly_the_original_area() {
+        for prefab in edge_cases(&CHARS) {
+            let map = ascii_map(&prefab);
+            assert_exact_coverage(&map, &bake(&map));
+        }
+    }
+
+    #[test]
+    fn chunks_cover_exactly_the_original_area() {
+        for prefab in edge_cases(&CHARS) {
+            let map = ascii_map(&prefab);
+            let mut colliders = Vec::new();
+            for y in 0..=(map.height - 1) / CHUNK_SIZE {
+                for x in 0..=(map.width - 1) / CHUNK_SIZE {
+                    let chunk = ChunkPos { x, y };
+                    assert!(map.has_chunk(chunk));
+                    colliders.extend(bake_colliders(&map, chunk.area(&map)));
+                }
+            }
+            assert_exact_coverage(&map, &colliders);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    camera_effects::CameraEffect,
+    constants::{BREAKABLE_HEALTH, EXPLOSION_DAMAGE, EXPLOSION_RADIUS, GRAVITY},
+};
+
+use super::{
+    events::{LevelLoaded, TerrainDamage, TilesChanged},
+    plugin::MapSpawner,
+    tileset::Tileset,
+    Map, TileType,
+};
+
+// pieces of debris spawned for each destroyed tile
+const DEBRIS_COUNT: usize = 6;
+// seconds before debris disappears
+const DEBRIS_LIFETIME: f32 = 0.8;
+
+/// damage taken so far by breakable tiles, by relative tile position
+#[derive(Resource, Default)]
+pub struct TileDamage {
+    pub damage: HashMap<(i32, i32), i32>,
+}
+
+/// a piece of a destroyed tile, falls and fades out
+#[derive(Component)]
+pub struct Debris {
+    pub velocity: Vec2,
+    pub timer: Timer,
+}
+
+/// the breakable tiles hit by some damage: the tile containing `pos`, and any other tile
+/// whose center is within `radius`
+pub fn tiles_damaged(map: &Map, damage: &TerrainDamage) -> Vec<(i32, i32)> {
+    let reach = damage.radius + map.tile_size as f32 / 2.;
+    let center = map.tile_coords(damage.pos);
+    map.tiles_overlapping(damage.pos, Vec2::splat(reach))
+        .into_iter()
+        .filter(|(x, y)| {
+            (*x, *y) == center || map.tile_position(*x, *y).distance(damage.pos) <= reach
+        })
+        .filter(|(x, y)| map.is_breakable(*x, *y))
+        .collect()
+}
+
+/// how much damage a breakable tile takes before it's destroyed, from its `health` property
+pub fn tile_health(map: &Map, x: i32, y: i32) -> i32 {
+    map.property(x, y, "health")
+        .and_then(|v| v.as_int())
+        .map(|v| v as i32)
+        .unwrap_or(BREAKABLE_HEALTH)
+}
+
+/// the explosion of a breakable tile with an `explosive` property, once it's destroyed
+///
+/// it reaches `EXPLOSION_RADIUS` unless the tile has an `explosion_radius` property.
+pub fn explosion(map: &Map, x: i32, y: i32) -> Option<TerrainDamage> {
+    let explosive = map
+        .property(x, y, "explosive")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if !explosive {
+        return None;
+    }
+    let radius = map
+        .property(x, y, "explosion_radius")
+        .and_then(|v| v.as_float())
+        .unwrap_or(EXPLOSION_RADIUS);
+    Some(TerrainDamage {
+        pos: map.tile_position(x, y),
+        radius,
+        amount: EXPLOSION_DAMAGE,
+    })
+}
+
+/// add up the damage taken by tiles, returning the tiles which were destroyed
+///
+/// explosive tiles explode as they're destroyed, which can destroy more tiles.
+pub fn destroyed_tiles(
+    map: &Map,
+    taken: &mut HashMap<(i32, i32), i32>,
+    damage: Vec<TerrainDamage>,
+) -> Vec<(i32, i32)> {
+    let mut pending = damage;
+    let mut destroyed = Vec::new();
+    while let Some(damage) = pending.pop() {
+        for (x, y) in tiles_damaged(map, &damage) {
+            if destroyed.contains(&(x, y)) {
+                continue;
+            }
+            let total = taken.entry((x, y)).or_insert(0);
+            *total += damage.amount;
+            if *total >= tile_health(map, x, y) {
+                destroyed.push((x, y));
+                pending.extend(explosion(map, x, y));
+            }
+        }
+    }
+    destroyed
+}
+
+/// apply terrain damage, destroying breakable tiles once they've taken enough
+#[allow(clippy::too_many_arguments)]
+pub fn break_tiles(
+    mut commands: Commands,
+    mut ev_damage: EventReader<TerrainDamage>,
+    mut ev_effects: EventWriter<CameraEffect>,
+    mut tile_damage: ResMut<TileDamage>,
+    mut map: ResMut<Map>,
+    mut spawner: MapSpawner,
+    tileset: Res<Tileset>,
+    asset_server: Res<AssetServer>,
+) {
+    let damage: Vec<TerrainDamage> = ev_damage.iter().cloned().collect();
+    let destroyed = destroyed_tiles(&map, &mut tile_damage.damage, damage);
+    if destroyed.is_empty() {
+        return;
+    }
+    if destroyed
+        .iter()
+        .any(|(x, y)| explosion(&map, *x, *y).is_some())
+    {
+        ev_effects.send(CameraEffect::Shake(0.6));
+    }
+
+    let mut new_map = map.clone();
+    for (x, y) in destroyed.iter() {
+        tile_damage.damage.remove(&(*x, *y));
+        if let Some(sprite) = tileset.sprite(&map, *x, *y) {
+            spawn_debris(
+                &mut commands,
+                &asset_server,
+                map.tile_position(*x, *y),
+                sprite.texture.as_str(),
+                sprite.sprite().color,
+            );
+        }
+        new_map.set_tile(*x, *y, TileType::Empty);
+    }
+    // this respawns the neighbours too, so they're auto-tiled again
+    spawner.replace(&mut map, new_map);
+}
+
+fn spawn_debris(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    pos: Vec2,
+    texture: &str,
+    color: Color,
+) {
+    let mut rng = rand::thread_rng();
+    for _ in 0..DEBRIS_COUNT {
+        let offset = Vec2::new(rng.gen_range(-8., 8.), rng.gen_range(-8., 8.));
+        commands.spawn((
+            Debris {
+                velocity: Vec2::new(rng.gen_range(-150., 150.), rng.gen_range(100., 350.)),
+                timer: Timer::from_seconds(DEBRIS_LIFETIME, TimerMode::Once),
+            },
+            SpriteBundle {
+                texture: asset_server.load(texture),
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(8., 8.)),
+                    color,
+                    ..default()
+                },
+                transform: Transform::from_xyz(pos.x + offset.x, pos.y + offset.y, 5.),
+                ..default()
+            },
+        ));
+    }
+}
+
+/// debris ignores collisions, it just falls through everything while fading out
+pub fn debris_movement(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Debris, &mut Transform, &mut Sprite)>,
+) {
+    let delta = time.delta_seconds();
+    for (entity, mut debris, mut transform, mut sprite) in query.iter_mut() {
+        debris.timer.tick(time.delta());
+        if debris.timer.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        debris.velocity.y += GRAVITY * delta;
+        transform.translation.x += debris.velocity.x * delta;
+        transform.translation.y += debris.velocity.y * delta;
+        transform.rotate_z(debris.velocity.x.signum() * 10. * delta);
+        sprite.color.set_a(debris.timer.percent_left());
+    }
+}
+
+/// damage done to the previous level's tiles, or to tiles which were replaced, doesn't carry
+/// over
+pub fn reset_tile_damage(
+    mut ev_loaded: EventReader<LevelLoaded>,
+    mut ev_changed: EventReader<TilesChanged>,
+    mut tile_damage: ResMut<TileDamage>,
+) {
+    if ev_loaded.iter().last().is_some() {
+        tile_damage.damage.clear();
+    }
+    for ev in ev_changed.iter() {
+        for pos in ev.tiles.iter() {
+            tile_damage.damage.remove(pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::properties::PropertyValue;
+
+    fn map() -> Map {
+        let mut map = Map::from_ascii(
+            "
+            -----
+            -BBB-
+            -#B#-
+            ",
+        );
+        map.position = Vec3::ZERO;
+        map
+    }
+
+    #[test]
+    fn damage_hits_the_tile_at_the_point() {
+        let map = map();
+        let damage = TerrainDamage {
+            pos: map.tile_position(2, 1) + Vec2::new(10., -10.),
+            radius: 0.,
+            amount: 1,
+        };
+        assert_eq!(tiles_damaged(&map, &damage), vec![(2, 1)]);
+
+        // not breakable
+        let damage = TerrainDamage {
+            pos: map.tile_position(1, 0),
+            radius: 0.,
+            amount: 1,
+        };
+        assert!(tiles_damaged(&map, &damage).is_empty());
+    }
+
+    #[test]
+    fn damage_radius() {
+        let map = map();
+        let damage = TerrainDamage {
+            pos: map.tile_position(2, 1),
+            radius: 32.,
+            amount: 1,
+        };
+        let mut tiles = tiles_damaged(&map, &damage);
+        tiles.sort();
+        assert_eq!(tiles, vec![(1, 1), (2, 0), (2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn health_property() {
+        let mut map = map();
+        assert_eq!(tile_health(&map, 1, 1), BREAKABLE_HEALTH);
+        map.set_property(1, 1, "health", PropertyValue::Int(10));
+        assert_eq!(tile_health(&map, 1, 1), 10);
+    }
+
+    fn hit(map: &Map, x: i32, y: i32) -> Vec<TerrainDamage> {
+        vec![TerrainDamage {
+            pos: map.tile_position(x, y),
+            radius: 0.,
+            amount: 1,
+        }]
+    }
+
+    #[test]
+    fn explosions() {
+        let mut map = map();
+        let mut taken = HashMap::new();
+        assert!(destroyed_tiles(&map, &mut taken, hit(&map, 1, 1)).is_empty());
+
+        map.set_property(1, 1, "explosive", PropertyValue::Bool(true));
+        map.set_property(1, 1, "health", PropertyValue::Int(1));
+        let mut taken = HashMap::new();
+        let mut destroyed = destroyed_tiles(&map, &mut taken, hit(&map, 1, 1));
+        destroyed.sort();
+        // the tiles around it, but not the one two tiles away
+        assert_eq!(destroyed, vec![(1, 1), (2, 0), (2, 1)]);
+
+        // setting off another explosion which reaches further
+        map.set_property(2, 1, "explosive", PropertyValue::Bool(true));
+        map.set_property(2, 1, "explosion_radius", PropertyValue::Int(32));
+        let mut taken = HashMap::new();
+        let mut destroyed = destroyed_tiles(&map, &mut taken, hit(&map, 1, 1));
+        destroyed.sort();
+        assert_eq!(destroyed, vec![(1, 1), (2, 0), (2, 1), (3, 1)]);
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+
+use crate::components::{Collider, Inventory, Player, RigidBody};
+
+use super::{events::ToggleSwitch, plugin::MapSpawner, Map, TileType};
+
+/// switches and plates open the doors on the same channel, set with the `channel` property
+///
+/// tiles without a channel are all on the default channel. switches flip the doors on their
+/// channel, unless it has plates: then its doors are open while any of its plates is pressed or
+/// any of its switches is on, see `channels_open`.
+pub fn channel(map: &Map, x: i32, y: i32) -> &str {
+    map.property(x, y, "channel")
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+}
+
+/// relative positions of every tile matching `include`
+fn find_tiles(map: &Map, include: impl Fn(i32, i32) -> bool) -> Vec<(i32, i32)> {
+    let mut tiles = Vec::new();
+    for y in 0..map.height {
+        for x in 0..map.width {
+            if include(x, y) {
+                tiles.push((x, y));
+            }
+        }
+    }
+    tiles
+}
+
+/// the tiles overlapped by a rigid body, doors don't close on these
+fn occupied_tiles<'a>(
+    map: &Map,
+    bodies: impl Iterator<Item = &'a Collider>,
+) -> HashSet<(i32, i32)> {
+    bodies
+        .flat_map(|c| map.tiles_overlapping(c.pos, c.half))
+        .collect()
+}
+
+/// the tile a door becomes when opened or closed, `None` if it's already that way or it'd be
+/// closed on something
+fn door_target(
+    map: &Map,
+    x: i32,
+    y: i32,
+    open: bool,
+    occupied: &HashSet<(i32, i32)>,
+) -> Option<TileType> {
+    match (map.tile(x, y), open) {
+        (TileType::Door, true) => Some(TileType::DoorOpen),
+        (TileType::DoorOpen, false) if !occupied.contains(&(x, y)) => Some(TileType::Door),
+        _ => None,
+    }
+}
+
+/// open or close a door, unless it's being closed on something. returns whether it changed.
+pub fn set_door(map: &mut Map, x: i32, y: i32, open: bool, occupied: &HashSet<(i32, i32)>) -> bool {
+    match door_target(map, x, y, open, occupied) {
+        Some(tile) => {
+            map.set_tile(x, y, tile);
+            true
+        }
+        None => false,
+    }
+}
+
+/// whether the doors on each channel with plates should be open: while any of its plates has
+/// something on it, or any of its switches is on
+fn channels_open<'a>(
+    map: &'a Map,
+    plates: &[(i32, i32)],
+    switches: &[(i32, i32)],
+    occupied: &HashSet<(i32, i32)>,
+) -> HashMap<&'a str, bool> {
+    let mut open: HashMap<&str, bool> = HashMap::new();
+    for (x, y) in plates.iter() {
+        *open.entry(channel(map, *x, *y)).or_default() |= occupied.contains(&(*x, *y));
+    }
+    for (x, y) in switches.iter() {
+        if let Some(open) = open.get_mut(channel(map, *x, *y)) {
+            *open |= map.tile(*x, *y) == TileType::SwitchOn;
+        }
+    }
+    open
+}
+
+/// flip a switch, and the doors on its channel
+pub fn toggle_switch(map: &mut Map, x: i32, y: i32, occupied: &HashSet<(i32, i32)>) -> bool {
+    let tile = match map.tile(x, y) {
+        TileType::Switch => TileType::SwitchOn,
+        TileType::SwitchOn => TileType::Switch,
+        _ => return false,
+    };
+    map.set_tile(x, y, tile);
+
+    let switch_channel = channel(map, x, y).to_string();
+    let on_channel = |tx: i32, ty: i32| channel(map, tx, ty) == switch_channel;
+    let plates = find_tiles(map, |tx, ty| map.is_plate(tx, ty) && on_channel(tx, ty));
+    let switches = find_tiles(map, |tx, ty| map.is_switch(tx, ty) && on_channel(tx, ty));
+    let doors = find_tiles(map, |tx, ty| map.is_door(tx, ty) && on_channel(tx, ty));
+
+    if plates.is_empty() {
+        for (dx, dy) in doors {
+            let open = map.tile(dx, dy) == TileType::Door;
+            set_door(map, dx, dy, open, occupied);
+        }
+    } else {
+        let open = channels_open(map, &plates, &switches, occupied)[switch_channel.as_str()];
+        for (dx, dy) in doors {
+            set_door(map, dx, dy, open, occupied);
+        }
+    }
+    true
+}
+
+/// players flip a switch they're standing in front of with E
+pub fn use_switches(
+    kb: Res<Input<KeyCode>>,
+    map: Res<Map>,
+    mut ev_toggle: EventWriter<ToggleSwitch>,
+    query: Query<&Collider, With<Player>>,
+) {
+    if !kb.just_pressed(KeyCode::E) {
+        return;
+    }
+    for collider in query.iter() {
+        let switch = map
+            .tiles_overlapping(collider.pos, collider.half)
+            .into_iter()
+            .find(|(x, y)| map.is_switch(*x, *y));
+        if let Some((x, y)) = switch {
+            ev_toggle.send(ToggleSwitch { x, y });
+        }
+    }
+}
+
+/// flip switches used by players or hit by projectiles
+pub fn toggle_switches(
+    mut ev_toggle: EventReader<ToggleSwitch>,
+    mut map: ResMut<Map>,
+    mut spawner: MapSpawner,
+    bodies: Query<&Collider, With<RigidBody>>,
+) {
+    let toggled: Vec<(i32, i32)> = ev_toggle.iter().map(|ev| (ev.x, ev.y)).collect();
+    if toggled.is_empty() {
+        return;
+    }
+
+    let occupied = occupied_tiles(&map, bodies.iter());
+    let mut new_map = map.clone();
+    let mut changed = false;
+    for (x, y) in toggled {
+        changed |= toggle_switch(&mut new_map, x, y, &occupied);
+    }
+    if changed {
+        spawner.replace(&mut map, new_map);
+    }
+}
+
+/// the plates, switches and doors in a map
+#[derive(Default)]
+pub struct ChannelTiles {
+    plates: Vec<(i32, i32)>,
+    switches: Vec<(i32, i32)>,
+    doors: Vec<(i32, i32)>,
+}
+
+/// open and close the doors on channels with plates, see `channels_open`
+pub fn pressure_plates(
+    mut map: ResMut<Map>,
+    mut spawner: MapSpawner,
+    mut tiles: Local<ChannelTiles>,
+    bodies: Query<&Collider, With<RigidBody>>,
+) {
+    // the tiles only move when the map does
+    if map.is_changed() {
+        *tiles = ChannelTiles {
+            plates: find_tiles(&map, |x, y| map.is_plate(x, y)),
+            switches: find_tiles(&map, |x, y| map.is_switch(x, y)),
+            doors: find_tiles(&map, |x, y| map.is_door(x, y)),
+        };
+    }
+    if tiles.plates.is_empty() {
+        return;
+    }
+
+    let occupied = occupied_tiles(&map, bodies.iter());
+    let open = channels_open(&map, &tiles.plates, &tiles.switches, &occupied);
+
+    let changes: Vec<(i32, i32, TileType)> = tiles
+        .doors
+        .iter()
+        .filter_map(|(x, y)| {
+            let open = *open.get(channel(&map, *x, *y))?;
+            door_target(&map, *x, *y, open, &occupied).map(|tile| (*x, *y, tile))
+        })
+        .collect();
+    if changes.is_empty() {
+        return;
+    }
+
+    let mut new_map = map.clone();
+    for (x, y, tile) in changes {
+        new_map.set_tile(x, y, tile);
+    }
+    spawner.replace(&mut map, new_map);
+}
+
+/// a player touching a locked door with a key opens it for good
+pub fn unlock_doors(
+    mut map: ResMut<Map>,
+    mut spawner: MapSpawner,
+    mut query: Query<(&Collider, &mut Inventory), With<Player>>,
+) {
+    let mut unlocked = Vec::new();
+    for (collider, mut inventory) in query.iter_mut() {
+        // locked doors are solid, so check just outside of the player
+        for (x, y) in map.tiles_overlapping(collider.pos, collider.half + 1.) {
+            if inventory.keys > 0
+                && map.tile(x, y) == TileType::LockedDoor
+                && !unlocked.contains(&(x, y))
+            {
+                inventory.keys -= 1;
+                unlocked.push((x, y));
+                println!("Unlocked door at {}, {}", x, y);
+            }
+        }
+    }
+    if unlocked.is_empty() {
+        return;
+    }
+
+    let mut new_map = map.clone();
+    for (x, y) in unlocked {
+        new_map.set_tile(x, y, TileType::Empty);
+    }
+    spawner.replace(&mut map, new_map);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::properties::PropertyValue;
+
+    fn map() -> Map {
+        let mut map = Map::from_ascii(
+            "
+            -w-D-D
+            _--D-d
+            ######
+            ",
+        );
+        // the door on the right, and the plate, are on their own channel
+        map.set_property(5, 2, "channel", PropertyValue::String("b".into()));
+        map.set_property(5, 1, "channel", PropertyValue::String("b".into()));
+        map.set_property(0, 1, "channel", PropertyValue::String("b".into()));
+        map
+    }
+
+    #[test]
+    fn switch_toggles_its_channel() {
+        let mut map = map();
+        assert!(toggle_switch(&mut map, 1, 2, &HashSet::new()));
+        assert_eq!(map.tile(1, 2), TileType::SwitchOn);
+        assert_eq!(map.tile(3, 2), TileType::DoorOpen);
+        assert_eq!(map.tile(3, 1), TileType::DoorOpen);
+        assert!(!map.is_obstacle(3, 1));
+        // other channel
+        assert_eq!(map.tile(5, 2), TileType::Door);
+        assert_eq!(map.tile(5, 1), TileType::DoorOpen);
+
+        // closing on something leaves that door open
+        let occupied = [(3, 1)].into_iter().collect();
+        toggle_switch(&mut map, 1, 2, &occupied);
+        assert_eq!(map.tile(1, 2), TileType::Switch);
+        assert_eq!(map.tile(3, 2), TileType::Door);
+        assert_eq!(map.tile(3, 1), TileType::DoorOpen);
+
+        assert!(!toggle_switch(&mut map, 0, 0, &HashSet::new()));
+    }
+
+    #[test]
+    fn plates_and_switches() {
+        let mut map = Map::from_ascii(
+            "
+            w_D
+            ###
+            ",
+        );
+        let none = HashSet::new();
+        let pressed: HashSet<(i32, i32)> = [(1, 1)].into_iter().collect();
+        let (plates, switches) = ([(1, 1)], [(0, 1)]);
+        assert!(!channels_open(&map, &plates, &switches, &none)[""]);
+        assert!(channels_open(&map, &plates, &switches, &pressed)[""]);
+
+        // the switch opens the door while nothing is on the plate
+        assert!(toggle_switch(&mut map, 0, 1, &none));
+        assert!(channels_open(&map, &plates, &switches, &none)[""]);
+        assert_eq!(map.tile(2, 1), TileType::DoorOpen);
+
+        // turning it off leaves the door open while the plate is pressed
+        toggle_switch(&mut map, 0, 1, &pressed);
+        assert_eq!(map.tile(0, 1), TileType::Switch);
+        assert_eq!(map.tile(2, 1), TileType::DoorOpen);
+
+        // and closes it once neither is on
+        toggle_switch(&mut map, 0, 1, &none);
+        toggle_switch(&mut map, 0, 1, &none);
+        assert_eq!(map.tile(2, 1), TileType::Door);
+
+        // switches on another channel don't count
+        map.set_tile(0, 1, TileType::SwitchOn);
+        map.set_property(0, 1, "channel", PropertyValue::String("b".into()));
+        assert!(!channels_open(&map, &plates, &switches, &none)[""]);
+
+        // and flip the doors on it, which has no plates
+        map.set_property(1, 1, "channel", PropertyValue::String("c".into()));
+        map.set_property(2, 1, "channel", PropertyValue::String("b".into()));
+        assert!(toggle_switch(&mut map, 0, 1, &none));
+        assert_eq!(map.tile(2, 1), TileType::DoorOpen);
+    }
+
+    #[test]
+    fn set_door() {
+        let mut map = map();
+        let none = HashSet::new();
+        assert!(super::set_door(&mut map, 5, 2, true, &none));
+        assert!(!super::set_door(&mut map, 5, 2, true, &none));
+        assert!(super::set_door(&mut map, 5, 2, false, &none));
+        assert!(map.is_obstacle(5, 2));
+        assert!(!super::set_door(&mut map, 0, 0, true, &none));
+    }
+}
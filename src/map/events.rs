@@ -2,8 +2,37 @@ use bevy::prelude::*;
 
 use crate::physics::CollisionEvent;
 
+/// sent once a new level has been loaded into `Map` and rendered
+pub struct LevelLoaded {
+    pub index: usize,
+}
+
+/// sent when the current level was changed on disk and `Map` was updated in place
+pub struct MapReloaded;
+
+/// sent when tiles of `Map` change while playing, e.g. broken, opened or painted in the editor
+pub struct TilesChanged {
+    /// relative tile positions
+    pub tiles: Vec<(i32, i32)>,
+}
+
+/// damages the breakable tiles within `radius` of `pos`, e.g. from a projectile (a radius of 0,
+/// only the tile it hit) or an explosive tile, see `destructible::explosion`
+#[derive(Clone)]
+pub struct TerrainDamage {
+    pub pos: Vec2,
+    pub radius: f32,
+    pub amount: i32,
+}
+
 pub fn tile_collision_listener(mut events: EventReader<CollisionEvent>) {
     for ev in events.iter() {
         info!("collision {:?} with {:?}", ev.entity, ev.collider);
     }
 }
+
+/// flip the switch at a relative tile position, see `doors`
+pub struct ToggleSwitch {
+    pub x: i32,
+    pub y: i32,
+}
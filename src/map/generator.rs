@@ -1,15 +1,37 @@
+use std::collections::{HashMap, HashSet};
+
 use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::{
+    background::BackgroundLayer,
+    chunks::{Chunk, ChunkPos, CHUNK_SIZE},
+    properties::TileProperties,
+    spawns::{EntitySpawn, Legend},
+};
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
 pub enum TileType {
     Empty,
     Platform,
     Ladder,
     Solid,
     Lava,
+    Exit,
+    /// solid until it's destroyed, see `destructible`
+    Breakable,
+    /// opened and closed by the switches and plates linked to it, see `doors`
+    Door,
+    DoorOpen,
+    /// solid until a player with a key touches it
+    LockedDoor,
+    Switch,
+    SwitchOn,
+    /// pressed while anything stands on it
+    Plate,
 }
 
-#[derive(Resource)]
+#[derive(Resource, Clone)]
 pub struct Map {
     pub position: Vec3,
 
@@ -17,8 +39,17 @@ pub struct Map {
     pub width: i32,
     pub height: i32,
     pub tile_size: i32,
-    pub tiles: Vec<TileType>,
+    /// the tiles in chunks, a row of chunks at a time from the bottom left, see `Map::tile`
+    pub chunks: Vec<Chunk>,
     pub starting_positions: Vec<Vec2>,
+    /// sparse properties by relative tile position, see `Map::property`
+    pub properties: HashMap<(i32, i32), TileProperties>,
+    /// entities placed in the map, see `Legend`
+    pub spawns: Vec<EntitySpawn>,
+    /// drawn behind the tiles, back to front
+    pub background: Vec<BackgroundLayer>,
+    /// light everywhere, without any light sources. 1 is fully lit, see `lighting`
+    pub ambient: f32,
 }
 
 impl Default for Map {
@@ -27,9 +58,13 @@ impl Default for Map {
             position: Vec3::ZERO,
             width: 0,
             height: 0,
-            tiles: vec![TileType::Empty; 0 as usize],
+            chunks: Vec::new(),
             tile_size: 32,
             starting_positions: vec![Vec2::ZERO; 4],
+            properties: HashMap::new(),
+            spawns: Vec::new(),
+            background: Vec::new(),
+            ambient: 1.,
         }
     }
 }
@@ -40,7 +75,7 @@ impl Map {
         Self {
             width,
             height,
-            tiles: vec![TileType::Empty; (width * height) as usize],
+            chunks: vec![Chunk::default(); Self::chunk_count(width, height)],
             ..default()
         }
     }
@@ -48,26 +83,34 @@ impl Map {
     pub fn from_position(position: Vec3, width: i32, height: i32) -> Self {
         Self {
             position,
-            width,
-            height,
-            tiles: vec![TileType::Empty; (width * height) as usize],
-            ..default()
+            ..Self::new(width, height)
         }
     }
 
+    /// position of a map of this size centered relative to 0/0
+    fn centered_position(width: i32, height: i32) -> Vec3 {
+        Vec3::new(
+            0.5 - width as f32 * 32.0 / 2.,
+            0.5 - height as f32 * 32.0 / 2.,
+            1.,
+        )
+    }
+
+    /// parse a prefab with the default `Legend`, levels bring their own
+    #[allow(unused)]
     pub fn from_prefab(prefab: (&str, i32, i32)) -> Self {
+        Self::from_prefab_with_legend(prefab, &Legend::default())
+    }
+
+    /// parse a prefab, with `legend` deciding which characters are entities
+    pub fn from_prefab_with_legend(prefab: (&str, i32, i32), legend: &Legend) -> Self {
         let mut map = Self::from_position(
-            // center the map relative to 0/0
-            Vec3::new(
-                0.5 - prefab.1 as f32 * 32.0 / 2.,
-                0.5 - prefab.2 as f32 * 32.0 / 2.,
-                1.,
-            ),
+            Self::centered_position(prefab.1, prefab.2),
             prefab.1,
             prefab.2,
         );
-        let mut new_tiles = map.tiles.clone();
         let mut starting_positions = Vec::new();
+        let mut spawns = Vec::new();
 
         let string_vec: Vec<char> = prefab
             .0
@@ -77,29 +120,135 @@ impl Map {
         let mut i = 0;
         for ty in 0..prefab.2 {
             for tx in 0..prefab.1 {
-                let idx = map.tile_index(tx, map.height - ty - 1);
-                let c = string_vec[i];
-                match c {
-                    '-' => new_tiles[idx] = TileType::Empty,
-                    '^' => new_tiles[idx] = TileType::Lava,
-                    '=' => new_tiles[idx] = TileType::Platform,
-                    '|' => new_tiles[idx] = TileType::Ladder,
+                let y = map.height - ty - 1;
+                // missing characters (e.g. a short row) are empty
+                let c = string_vec.get(i).copied().unwrap_or('-');
+                let tile = match c {
+                    '-' => TileType::Empty,
+                    '^' => TileType::Lava,
+                    '=' => TileType::Platform,
+                    '|' => TileType::Ladder,
                     'X' => {
-                        starting_positions.push(map.tile_position(tx, map.height - ty - 1));
-                        new_tiles[idx] = TileType::Empty
+                        starting_positions.push(map.tile_position(tx, y));
+                        TileType::Empty
                     }
-                    '#' => new_tiles[idx] = TileType::Solid,
-                    _ => println!("No idea what to do with [{}]", c),
-                }
+                    '#' => TileType::Solid,
+                    'E' => TileType::Exit,
+                    'B' => TileType::Breakable,
+                    'D' => TileType::Door,
+                    'd' => TileType::DoorOpen,
+                    'L' => TileType::LockedDoor,
+                    'w' => TileType::Switch,
+                    'W' => TileType::SwitchOn,
+                    '_' => TileType::Plate,
+                    _ => {
+                        match legend.get(c) {
+                            Some(kind) => spawns.push(EntitySpawn { kind, x: tx, y }),
+                            None => println!("No idea what to do with [{}]", c),
+                        }
+                        TileType::Empty
+                    }
+                };
+                map.set_tile(tx, y, tile);
                 i += 1;
             }
         }
 
         map.starting_positions = starting_positions;
-        map.tiles = new_tiles;
+        map.spawns = spawns;
         map
     }
 
+    /// parse a prefab, using the number of (non-empty) lines as the height and
+    /// the length of the first line as the width
+    #[allow(unused)]
+    pub fn from_ascii(prefab: &str) -> Self {
+        Self::from_ascii_with_legend(prefab, &Legend::default())
+    }
+
+    pub fn from_ascii_with_legend(prefab: &str, legend: &Legend) -> Self {
+        let rows: Vec<&str> = prefab
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .collect();
+        let width = rows.first().map(|r| r.chars().count()).unwrap_or(0);
+        Self::from_prefab_with_legend((prefab, width as i32, rows.len() as i32), legend)
+    }
+
+    /// the map as an ascii prefab, the inverse of `from_prefab`
+    ///
+    /// rows are written top row first, each ending with a newline. starting positions are
+    /// written as `X` and entities with their character in the default `Legend`, replacing
+    /// whatever tile they're on.
+    pub fn to_prefab(&self) -> String {
+        let starting_positions: HashSet<(i32, i32)> = self
+            .starting_positions
+            .iter()
+            .map(|p| self.tile_coords(*p))
+            .collect();
+        let markers: HashMap<(i32, i32), char> = self
+            .spawns
+            .iter()
+            .map(|s| ((s.x, s.y), s.kind.marker()))
+            .collect();
+
+        let mut out = String::with_capacity(((self.width + 1) * self.height) as usize);
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let c = if starting_positions.contains(&(x, y)) {
+                    'X'
+                } else if let Some(marker) = markers.get(&(x, y)) {
+                    *marker
+                } else {
+                    match self.tile(x, y) {
+                        TileType::Empty => '-',
+                        TileType::Platform => '=',
+                        TileType::Ladder => '|',
+                        TileType::Solid => '#',
+                        TileType::Lava => '^',
+                        TileType::Exit => 'E',
+                        TileType::Breakable => 'B',
+                        TileType::Door => 'D',
+                        TileType::DoorOpen => 'd',
+                        TileType::LockedDoor => 'L',
+                        TileType::Switch => 'w',
+                        TileType::SwitchOn => 'W',
+                        TileType::Plate => '_',
+                    }
+                };
+                out.push(c);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// relative positions of the tiles which differ between two maps of the same size,
+    /// or `None` if the size is different
+    pub fn diff(&self, other: &Map) -> Option<Vec<(i32, i32)>> {
+        if self.width != other.width || self.height != other.height {
+            return None;
+        }
+        let mut changed = Vec::new();
+        for (i, (a, b)) in self.chunks.iter().zip(other.chunks.iter()).enumerate() {
+            // most edits only touch a chunk or two
+            if a == b {
+                continue;
+            }
+            let chunk = self.chunk_at(i);
+            let area = chunk.area(self);
+            for y in area.y0..=area.y1 {
+                for x in area.x0..=area.x1 {
+                    if self.tile(x, y) != other.tile(x, y) {
+                        changed.push((x, y));
+                    }
+                }
+            }
+        }
+        Some(changed)
+    }
+
     /// returns the the tile located at map position
     pub fn tile_at_point(&self, point: Vec2) -> Vec2 {
         Vec2::new(
@@ -124,9 +273,85 @@ impl Map {
         )
     }
 
-    /// translate relative tile position to index in tilemap
-    pub fn tile_index(&self, x: i32, y: i32) -> usize {
-        ((y * self.width) + x) as usize
+    /// relative position of the tile containing a point (as rendered, centered on `tile_position`)
+    pub fn tile_coords(&self, point: Vec2) -> (i32, i32) {
+        let tile_size = self.tile_size as f32;
+        (
+            ((point.x - self.position.x) / tile_size + 0.5).floor() as i32,
+            ((point.y - self.position.y) / tile_size + 0.5).floor() as i32,
+        )
+    }
+
+    /// bottom left and top right corners of the map in the world
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        let tile_size = self.tile_size as f32;
+        let min = self.tile_position(0, 0) - tile_size / 2.;
+        (
+            min,
+            min + Vec2::new(self.width as f32, self.height as f32) * tile_size,
+        )
+    }
+
+    pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && x < self.width && y >= 0 && y < self.height
+    }
+
+    pub fn set_tile(&mut self, x: i32, y: i32, tile: TileType) {
+        if self.in_bounds(x, y) {
+            let chunk = ChunkPos::of_tile(x, y);
+            let idx = self.chunk_index(chunk);
+            self.chunks[idx].set_tile(x - chunk.x * CHUNK_SIZE, y - chunk.y * CHUNK_SIZE, tile);
+        }
+    }
+
+    /// a copy of the map with a different size, keeping tiles relative to the bottom left
+    /// corner and re-centering it. starting positions, properties and entities outside of the
+    /// new size are dropped.
+    pub fn resized(&self, width: i32, height: i32) -> Map {
+        let mut map = Self::from_position(Self::centered_position(width, height), width, height);
+        map.tile_size = self.tile_size;
+        for y in 0..height.min(self.height) {
+            for x in 0..width.min(self.width) {
+                map.set_tile(x, y, self.tile(x, y));
+            }
+        }
+        map.starting_positions = self
+            .starting_positions
+            .iter()
+            .map(|p| self.tile_coords(*p))
+            .filter(|(x, y)| map.in_bounds(*x, *y))
+            .map(|(x, y)| map.tile_position(x, y))
+            .collect();
+        map.properties = self
+            .properties
+            .iter()
+            .filter(|((x, y), _)| map.in_bounds(*x, *y))
+            .map(|(pos, properties)| (*pos, properties.clone()))
+            .collect();
+        map.spawns = self
+            .spawns
+            .iter()
+            .filter(|s| map.in_bounds(s.x, s.y))
+            .copied()
+            .collect();
+        map.background = self.background.clone();
+        map.ambient = self.ambient;
+        map
+    }
+
+    /// relative positions of the tiles overlapping a box (as rendered, centered on `tile_position`)
+    pub fn tiles_overlapping(&self, pos: Vec2, half: Vec2) -> Vec<(i32, i32)> {
+        // shrink the box slightly so touching the edge of a tile isn't overlapping it
+        let (min_x, min_y) = self.tile_coords(pos - half + 0.01);
+        let (max_x, max_y) = self.tile_coords(pos + half - 0.01);
+
+        let mut tiles = Vec::new();
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                tiles.push((x, y));
+            }
+        }
+        tiles
     }
 
     // return the tile at relative position
@@ -134,13 +359,29 @@ impl Map {
         if x < 0 || x >= self.width || y < 0 || y >= self.height {
             TileType::Solid
         } else {
-            self.tiles[self.tile_index(x, y)]
+            let chunk = ChunkPos::of_tile(x, y);
+            self.chunks[self.chunk_index(chunk)]
+                .tile(x - chunk.x * CHUNK_SIZE, y - chunk.y * CHUNK_SIZE)
         }
     }
 
     pub fn is_obstacle(&self, x: i32, y: i32) -> bool {
         let tile = self.tile(x, y);
-        tile == TileType::Solid || tile == TileType::Lava
+        matches!(
+            tile,
+            TileType::Solid | TileType::Breakable | TileType::Door | TileType::LockedDoor
+        )
+    }
+
+    pub fn is_breakable(&self, x: i32, y: i32) -> bool {
+        let tile = self.tile(x, y);
+        tile == TileType::Breakable
+    }
+
+    /// tiles that hurt whoever touches them
+    pub fn is_hazard(&self, x: i32, y: i32) -> bool {
+        let tile = self.tile(x, y);
+        tile == TileType::Lava
     }
 
     pub fn is_platform(&self, x: i32, y: i32) -> bool {
@@ -153,11 +394,33 @@ impl Map {
         tile == TileType::Ladder
     }
 
+    pub fn is_exit(&self, x: i32, y: i32) -> bool {
+        let tile = self.tile(x, y);
+        tile == TileType::Exit
+    }
+
+    /// open or closed
+    pub fn is_door(&self, x: i32, y: i32) -> bool {
+        let tile = self.tile(x, y);
+        tile == TileType::Door || tile == TileType::DoorOpen
+    }
+
+    /// on or off
+    pub fn is_switch(&self, x: i32, y: i32) -> bool {
+        let tile = self.tile(x, y);
+        tile == TileType::Switch || tile == TileType::SwitchOn
+    }
+
+    pub fn is_plate(&self, x: i32, y: i32) -> bool {
+        let tile = self.tile(x, y);
+        tile == TileType::Plate
+    }
+
     pub fn is_ground(&self, x: i32, y: i32) -> bool {
         if x < 0 || x >= self.width || y < 0 || y >= self.height {
             false
         } else {
-            self.tiles[((y * self.width) + x) as usize] == TileType::Solid
+            self.is_obstacle(x, y)
         }
     }
 
@@ -167,37 +430,10 @@ impl Map {
     }
 }
 
-// position is bottom left corner
-const DEFAULT_MAP: (&str, i32, i32) = (
-    "
-------------------------------------------------
-------------------------------------------------
-------------------------------------------------
-------------------------------------------------
--------------#####------------------------------
----------------------====---------==------------
------------------------------###----------------
----------------#----##----#-------==------------
----------------#----------#---------------------
----------------####====####----#--==------------
---------------X----------------#----------------
-----------###########################-----------
-------------------------------------------------
-----===---------------------------------===-----
-------------------------------------------------
-^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
-",
-    48,
-    16,
-);
-
-pub fn build_default_map() -> Map {
-    Map::from_prefab(DEFAULT_MAP)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::map::{properties::PropertyValue, test_maps::edge_cases};
 
     #[test]
     fn test_integration() {
@@ -219,7 +455,9 @@ mod tests {
         assert_eq!(map.tile(1, 1), TileType::Solid);
         assert_eq!(map.tile(1, 2), TileType::Empty);
 
-        assert_eq!(map.is_obstacle(0, 0), true);
+        assert_eq!(map.is_obstacle(0, 0), false);
+        assert_eq!(map.is_hazard(0, 0), true);
+        assert_eq!(map.is_hazard(1, 1), false);
         assert_eq!(map.is_obstacle(1, 1), true);
         assert_eq!(map.is_obstacle(2, 1), true);
         assert_eq!(map.is_obstacle(0, 1), false);
@@ -233,12 +471,126 @@ mod tests {
     }
 
     #[test]
-    fn tile_index() {
-        let mut map = Map::new(32, 16);
-        map.tile_size = 16;
-        assert_eq!(map.tile_index(0, 0), 0);
-        assert_eq!(map.tile_index(3, 15), 483);
-        assert_eq!(map.tile_index(35, 13), 451);
+    fn from_ascii() {
+        let map = Map::from_ascii(
+            "
+            -X-E
+            -##-
+            ^^^^
+            ",
+        );
+        assert_eq!(map.width, 4);
+        assert_eq!(map.height, 3);
+        assert_eq!(map.tile(3, 2), TileType::Exit);
+        assert_eq!(map.tile(1, 1), TileType::Solid);
+        assert_eq!(map.starting_positions.len(), 1);
+    }
+
+    #[test]
+    fn to_prefab() {
+        let prefab = "-X-E\n-#B=\n^^||\n";
+        let map = Map::from_ascii(prefab);
+        assert_eq!(map.to_prefab(), prefab);
+        // the top row is written first
+        assert_eq!(map.tile(3, 2), TileType::Exit);
+        assert_eq!(map.tile(0, 0), TileType::Lava);
+    }
+
+    #[test]
+    fn prefab_round_trip() {
+        let chars = [
+            '-', '#', '=', '|', '^', 'E', 'B', 'D', 'd', 'L', 'w', 'W', '_', 'X', 'C', 'S', 'K',
+            'H', '$', 'P',
+        ];
+        for prefab in edge_cases(&chars) {
+            let map = Map::from_ascii(&prefab);
+            let written = map.to_prefab();
+            assert_eq!(written, prefab);
+
+            let parsed = Map::from_ascii(&written);
+            assert_eq!(parsed.width, map.width);
+            assert_eq!(parsed.height, map.height);
+            assert_eq!(parsed.position, map.position);
+            assert_eq!(parsed.chunks, map.chunks);
+            assert_eq!(parsed.starting_positions, map.starting_positions);
+            assert_eq!(parsed.spawns, map.spawns);
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let map = Map::from_ascii(
+            "
+            -X--
+            -##-
+            ^^^^
+            ",
+        );
+        let changed = Map::from_ascii(
+            "
+            -X-#
+            -#--
+            ^^^^
+            ",
+        );
+        assert_eq!(map.diff(&map), Some(vec![]));
+        assert_eq!(map.diff(&changed), Some(vec![(2, 1), (3, 2)]));
+        assert_eq!(map.diff(&Map::from_ascii("----")), None);
+    }
+
+    #[test]
+    fn resized() {
+        let mut map = Map::from_ascii(
+            "
+            ---X
+            -##-
+            ^^^^
+            ",
+        );
+        map.set_property(3, 0, "damage", PropertyValue::Int(5));
+        let bigger = map.resized(6, 4);
+        assert_eq!(bigger.width, 6);
+        assert_eq!(bigger.height, 4);
+        assert_eq!(bigger.tile(1, 1), TileType::Solid);
+        assert_eq!(bigger.tile(5, 0), TileType::Empty);
+        assert_eq!(
+            bigger.position,
+            Map::from_ascii("------\n------\n------\n------").position
+        );
+        assert_eq!(bigger.starting_positions, vec![bigger.tile_position(3, 2)]);
+        assert!(bigger.property(3, 0, "damage").is_some());
+
+        let smaller = map.resized(2, 2);
+        assert_eq!(smaller.tile(1, 1), TileType::Solid);
+        assert!(smaller.starting_positions.is_empty());
+        assert!(smaller.properties.is_empty());
+    }
+
+    #[test]
+    fn short_rows_are_empty() {
+        let map = Map::from_prefab(("##\n#", 2, 2));
+        assert_eq!(map.tile(0, 0), TileType::Solid);
+        assert_eq!(map.tile(1, 0), TileType::Empty);
+    }
+
+    #[test]
+    fn chunked_tiles() {
+        let mut map = Map::new(40, 20);
+        assert_eq!(map.chunks.len(), 6);
+        // either side of the borders between chunks
+        for (x, y) in [(15, 0), (16, 0), (0, 15), (0, 16), (39, 19), (32, 16)] {
+            map.set_tile(x, y, TileType::Solid);
+        }
+        for (x, y) in [(15, 0), (16, 0), (0, 15), (0, 16), (39, 19), (32, 16)] {
+            assert_eq!(map.tile(x, y), TileType::Solid);
+        }
+        assert_eq!(map.tile(17, 0), TileType::Empty);
+        assert_eq!(map.tile(40, 0), TileType::Solid);
+
+        let mut changed = map.clone();
+        changed.set_tile(39, 19, TileType::Empty);
+        changed.set_tile(1, 1, TileType::Lava);
+        assert_eq!(map.diff(&changed), Some(vec![(1, 1), (39, 19)]));
     }
 
     #[test]
@@ -250,6 +602,28 @@ mod tests {
         assert_eq!(map.tile_position(0, 0), Vec2::new(-256., -128.));
     }
 
+    #[test]
+    fn tiles_overlapping() {
+        let mut map = Map::new(32, 16);
+        map.tile_size = 16;
+        map.position = Vec3::new(-256., -128., 0.);
+        // fully inside a single tile
+        assert_eq!(
+            map.tiles_overlapping(Vec2::new(-256., -128.), Vec2::new(4., 4.)),
+            vec![(0, 0)]
+        );
+        // straddling the edge between two columns
+        assert_eq!(
+            map.tiles_overlapping(Vec2::new(-248., -128.), Vec2::new(4., 4.)),
+            vec![(0, 0), (1, 0)]
+        );
+        // touching, but not overlapping, the tile above
+        assert_eq!(
+            map.tiles_overlapping(Vec2::new(-256., -124.), Vec2::new(4., 4.)),
+            vec![(0, 0)]
+        );
+    }
+
     #[test]
     fn tile_at_point() {
         let mut map = Map::new(32, 16);
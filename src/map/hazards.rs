@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    camera_effects::CameraEffect,
+    components::{Collider, Health, Invulnerable, Player, PlayerState},
+    constants::{HAZARD_EFFECT_INTERVAL, HAZARD_INVULNERABILITY, LAVA_DAMAGE},
+};
+
+use super::Map;
+
+/// damage players overlapping a hazard tile, by the tile's `damage` property if it has one
+///
+/// hazards don't have colliders, so the overlap is tested against the map directly. the damage
+/// is dealt as a hit, after which a surviving player is invulnerable for
+/// `HAZARD_INVULNERABILITY`, and the camera only shakes and flashes every
+/// `HAZARD_EFFECT_INTERVAL`.
+pub fn hazard_damage(
+    mut commands: Commands,
+    mut query: Query<(Entity, &Collider, &Player, &mut Health), Without<Invulnerable>>,
+    map: Res<Map>,
+    time: Res<Time>,
+    mut last_effects: Local<HashMap<Entity, f32>>,
+    mut ev_effects: EventWriter<CameraEffect>,
+) {
+    let now = time.elapsed_seconds();
+    for (entity, collider, player, mut health) in query.iter_mut() {
+        if player.state == PlayerState::Dead {
+            continue;
+        }
+        let damage = map
+            .tiles_overlapping(collider.pos, collider.half)
+            .iter()
+            .filter(|(x, y)| map.is_hazard(*x, *y))
+            .map(|(x, y)| {
+                map.property(*x, *y, "damage")
+                    .and_then(|v| v.as_int())
+                    .map(|v| v as i32)
+                    .unwrap_or(LAVA_DAMAGE)
+            })
+            .max();
+        if let Some(damage) = damage {
+            health.damage(damage);
+            let due = match last_effects.get(&entity) {
+                Some(last) => now - last >= HAZARD_EFFECT_INTERVAL,
+                None => true,
+            };
+            if due {
+                ev_effects.send(CameraEffect::Shake(0.4));
+                ev_effects.send(CameraEffect::Flash(Color::rgba(1., 0.1, 0., 0.35)));
+                last_effects.insert(entity, now);
+            }
+            if !health.is_dead() {
+                commands.entity(entity).insert(Invulnerable {
+                    timer: Timer::from_seconds(HAZARD_INVULNERABILITY, TimerMode::Once),
+                });
+            }
+        }
+    }
+}
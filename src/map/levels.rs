@@ -0,0 +1,406 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    assets::asset_path,
+    components::{Collider, Player, Projectile},
+};
+
+use super::{
+    background::BackgroundLayer,
+    events::LevelLoaded,
+    plugin::MapSpawner,
+    properties::TileProperties,
+    spawns::{Legend, SpawnKind},
+    Map,
+};
+
+pub const DEFAULT_CAMPAIGN: &str = "maps/campaign.ron";
+
+// seconds to fade out, and then back in, when changing level
+const TRANSITION_DURATION: f32 = 0.5;
+
+/// a level file, see `assets/maps`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Level {
+    #[serde(default)]
+    pub name: String,
+    /// ascii prefab, see `Map::from_prefab`
+    #[serde(with = "tile_rows")]
+    pub tiles: String,
+    /// how lit the level is without any light sources, from 0 (pitch black) to 1
+    #[serde(default = "default_ambient")]
+    pub ambient: f32,
+    /// image layers drawn behind the map, back to front
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub background: Vec<BackgroundLayer>,
+    /// properties by tile position, counting from the bottom left tile at (0, 0)
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted_properties"
+    )]
+    pub properties: HashMap<(i32, i32), TileProperties>,
+    /// extra entity markers, on top of the default `Legend`
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted"
+    )]
+    pub legend: HashMap<char, SpawnKind>,
+}
+
+fn default_ambient() -> f32 {
+    1.
+}
+
+/// the tiles are read from a multi-line string or a list of rows, and written as rows since
+/// ron would escape the newlines in a string
+mod tile_rows {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(tiles: &str, serializer: S) -> Result<S::Ok, S::Error> {
+        let rows: Vec<&str> = tiles
+            .lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .collect();
+        rows.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Tiles {
+            Prefab(String),
+            Rows(Vec<String>),
+        }
+
+        Ok(match Tiles::deserialize(deserializer)? {
+            Tiles::Prefab(prefab) => prefab,
+            Tiles::Rows(rows) => rows.join("\n"),
+        })
+    }
+}
+
+// maps are written sorted, so saving the same level twice writes the same file
+fn sorted<S, K, V>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    K: Ord + Serialize,
+    V: Serialize,
+{
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+fn sorted_properties<S: Serializer>(
+    properties: &HashMap<(i32, i32), TileProperties>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    properties
+        .iter()
+        .map(|(pos, properties)| (pos, properties.iter().collect::<BTreeMap<_, _>>()))
+        .collect::<BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
+impl Level {
+    pub fn try_load(path: &str) -> Result<Self, String> {
+        let full_path = asset_path(path);
+        let contents = std::fs::read_to_string(&full_path)
+            .map_err(|e| format!("unable to read level {:?}: {}", full_path, e))?;
+        ron::from_str(&contents)
+            .map_err(|e| format!("unable to parse level {:?}: {}", full_path, e))
+    }
+
+    pub fn load(path: &str) -> Self {
+        Self::try_load(path).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn to_map(&self) -> Map {
+        let mut map = Map::from_ascii_with_legend(&self.tiles, &Legend::with_markers(&self.legend));
+        for ((x, y), properties) in self.properties.iter() {
+            for (key, value) in properties.iter() {
+                map.set_property(*x, *y, key, value.clone());
+            }
+        }
+        map.background = self.background.clone();
+        map.ambient = self.ambient;
+        map
+    }
+
+    pub fn to_ron(&self) -> Result<String, String> {
+        ron::ser::to_string_pretty(self, PrettyConfig::new()).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let full_path = asset_path(path);
+        let contents = self
+            .to_ron()
+            .map_err(|e| format!("unable to write level {:?}: {}", full_path, e))?;
+        std::fs::write(&full_path, contents)
+            .map_err(|e| format!("unable to write level {:?}: {}", full_path, e))
+    }
+}
+
+/// the sequence of levels to play through
+#[derive(Resource, Clone, Debug, Deserialize)]
+pub struct Campaign {
+    pub levels: Vec<String>,
+    #[serde(skip)]
+    pub current: usize,
+}
+
+impl Campaign {
+    pub fn load(path: &str) -> Self {
+        let full_path = asset_path(path);
+        let contents = std::fs::read_to_string(&full_path)
+            .unwrap_or_else(|e| panic!("unable to read campaign {:?}: {}", full_path, e));
+        Self::parse(&contents)
+            .unwrap_or_else(|e| panic!("unable to parse campaign {:?}: {}", full_path, e))
+    }
+
+    /// a campaign needs at least one level to play
+    fn parse(contents: &str) -> Result<Self, String> {
+        let campaign: Self = ron::from_str(contents).map_err(|e| e.to_string())?;
+        if campaign.levels.is_empty() {
+            return Err("no levels".to_string());
+        }
+        Ok(campaign)
+    }
+
+    pub fn current_path(&self) -> &str {
+        &self.levels[self.current]
+    }
+
+    pub fn current_level(&self) -> Level {
+        Level::load(self.current_path())
+    }
+
+    /// move on to the next level, going back to the first one after the last
+    pub fn advance(&mut self) {
+        self.current = (self.current + 1) % self.levels.len();
+    }
+}
+
+pub fn build_default_campaign() -> Campaign {
+    Campaign::load(DEFAULT_CAMPAIGN)
+}
+
+#[derive(Default, PartialEq, Eq)]
+pub enum TransitionState {
+    #[default]
+    Idle,
+    FadeOut,
+    FadeIn,
+}
+
+#[derive(Resource, Default)]
+pub struct LevelTransition {
+    pub state: TransitionState,
+    pub timer: Timer,
+}
+
+impl LevelTransition {
+    fn start(&mut self, state: TransitionState) {
+        self.state = state;
+        self.timer = Timer::from_seconds(TRANSITION_DURATION, TimerMode::Once);
+    }
+}
+
+/// full screen overlay used to fade between levels
+#[derive(Component)]
+pub struct TransitionOverlay;
+
+pub fn setup_transition_overlay(mut commands: Commands) {
+    commands.spawn((
+        TransitionOverlay,
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                ..default()
+            },
+            background_color: Color::NONE.into(),
+            z_index: ZIndex::Global(100),
+            ..default()
+        },
+    ));
+}
+
+/// start changing level when a player reaches an exit
+pub fn level_exit(
+    map: Res<Map>,
+    mut transition: ResMut<LevelTransition>,
+    query: Query<&Collider, With<Player>>,
+) {
+    if transition.state != TransitionState::Idle {
+        return;
+    }
+    for collider in query.iter() {
+        let at_exit = map
+            .tiles_overlapping(collider.pos, collider.half)
+            .iter()
+            .any(|(x, y)| map.is_exit(*x, *y));
+        if at_exit {
+            transition.start(TransitionState::FadeOut);
+            return;
+        }
+    }
+}
+
+/// fade out, swap the level, then fade back in
+#[allow(clippy::too_many_arguments)]
+pub fn level_transition(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut transition: ResMut<LevelTransition>,
+    mut campaign: ResMut<Campaign>,
+    mut map: ResMut<Map>,
+    mut spawner: MapSpawner,
+    mut ev_loaded: EventWriter<LevelLoaded>,
+    projectile_query: Query<Entity, With<Projectile>>,
+    mut overlay_query: Query<&mut BackgroundColor, With<TransitionOverlay>>,
+) {
+    if transition.state == TransitionState::Idle {
+        return;
+    }
+
+    transition.timer.tick(time.delta());
+    let alpha = match transition.state {
+        TransitionState::FadeOut => transition.timer.percent(),
+        _ => transition.timer.percent_left(),
+    };
+    for mut color in overlay_query.iter_mut() {
+        color.0 = Color::rgba(0., 0., 0., alpha);
+    }
+
+    if !transition.timer.finished() {
+        return;
+    }
+
+    match transition.state {
+        TransitionState::FadeOut => {
+            for entity in projectile_query.iter() {
+                commands.entity(entity).despawn_recursive();
+            }
+
+            let previous = campaign.current;
+            campaign.advance();
+            match Level::try_load(campaign.current_path()) {
+                Ok(level) => {
+                    println!("Loading level {} ({})", campaign.current, level.name);
+                    spawner.load(&mut map, level.to_map());
+                    ev_loaded.send(LevelLoaded {
+                        index: campaign.current,
+                    });
+                }
+                Err(e) => {
+                    println!("Staying on level {}: {}", previous, e);
+                    campaign.current = previous;
+                }
+            }
+
+            transition.start(TransitionState::FadeIn);
+        }
+        _ => transition.state = TransitionState::Idle,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_round_trip() {
+        let level: Level = ron::from_str(
+            r#"(
+                name: "Test",
+                tiles: "
+                    -X-k
+                    ##^#
+                ",
+                ambient: 0.5,
+                background: [(texture: "wall_mid.png", size: (64.0, 64.0))],
+                properties: {
+                    (2, 0): { "damage": 25, "name": "pit" },
+                    (0, 0): { "slippery": true },
+                },
+                legend: { 'k': Pickup(Key), 'c': Crate },
+            )"#,
+        )
+        .unwrap();
+        let written = level.to_ron().unwrap();
+        let parsed: Level = ron::from_str(&written).unwrap();
+        assert_eq!(parsed.name, level.name);
+        assert_eq!(parsed.tiles, "-X-k\n##^#");
+        assert_eq!(parsed.ambient, level.ambient);
+        assert_eq!(parsed.background, level.background);
+        assert_eq!(parsed.properties, level.properties);
+        assert_eq!(parsed.legend, level.legend);
+        assert_eq!(parsed.to_map().chunks, level.to_map().chunks);
+        assert_eq!(parsed.to_map().spawns, level.to_map().spawns);
+
+        // written the same way every time
+        assert_eq!(parsed.to_ron().unwrap(), written);
+    }
+
+    #[test]
+    fn level_properties() {
+        let level: Level = ron::from_str(
+            r#"(
+                tiles: "
+                    ---
+                    ^^^
+                ",
+                properties: {
+                    (1, 0): { "damage": 25, "name": "pit" },
+                    (5, 5): { "ignored": true },
+                },
+            )"#,
+        )
+        .unwrap();
+        let map = level.to_map();
+        assert_eq!(map.property(1, 0, "damage").unwrap().as_int(), Some(25));
+        assert_eq!(map.property(1, 0, "name").unwrap().as_str(), Some("pit"));
+        // outside of the map
+        assert!(map.properties(5, 5).is_none());
+
+        let mut level = level;
+        level.properties = map.properties.clone();
+        let parsed: Level = ron::from_str(&level.to_ron().unwrap()).unwrap();
+        assert_eq!(parsed.to_map().properties, map.properties);
+    }
+
+    #[test]
+    fn campaign_levels() {
+        let campaign = Campaign::parse(r#"(levels: ["maps/01.ron", "maps/02.ron"])"#).unwrap();
+        assert_eq!(campaign.levels.len(), 2);
+        assert_eq!(campaign.current_path(), "maps/01.ron");
+        assert!(Campaign::parse("(levels: [])").is_err());
+        assert!(Campaign::parse("(").is_err());
+    }
+
+    #[test]
+    fn level_legend() {
+        let level: Level = ron::from_str(
+            r#"(
+                tiles: "
+                    -c-
+                    C##
+                ",
+                legend: { 'c': Pickup(Coin) },
+            )"#,
+        )
+        .unwrap();
+        let map = level.to_map();
+        assert_eq!(map.spawns.len(), 2);
+
+        let parsed: Level = ron::from_str(&level.to_ron().unwrap()).unwrap();
+        assert_eq!(parsed.legend, level.legend);
+        assert_eq!(parsed.to_map().spawns, map.spawns);
+    }
+}
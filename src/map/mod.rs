@@ -1,6 +1,26 @@
+mod animation;
+mod autotile;
+mod background;
+mod chunks;
+mod colliders;
+mod destructible;
+mod doors;
 mod events;
 mod generator;
+mod hazards;
+mod levels;
+mod navigation;
 mod plugin;
+mod properties;
+mod reload;
+mod spawns;
+#[cfg(test)]
+mod test_maps;
+mod tileset;
 
-pub use self::generator::Map;
-pub use self::plugin::MapPlugin;
+pub use self::events::{LevelLoaded, MapReloaded, TerrainDamage, ToggleSwitch};
+pub use self::generator::{Map, TileType};
+pub use self::levels::{Campaign, Level};
+pub use self::navigation::{Movement, NavAction, NavGraph, NavStep};
+pub use self::plugin::{MapPlugin, MapSpawner};
+pub use self::spawns::{EnemyKind, EntitySpawn, PickupKind, SpawnKind};
@@ -0,0 +1,443 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    components::Player,
+    constants::{GRAVITY, PLAYER_RUN_SPEED},
+};
+
+use super::Map;
+
+/// how a character gets from one tile to the next
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NavAction {
+    /// to the tile beside it
+    Walk,
+    /// walk off a ledge and fall straight down
+    Fall,
+    /// fall through the platform underneath
+    Drop,
+    /// up or down a ladder
+    Climb,
+    /// jump with this speed, in pixels per second
+    Jump { speed: Vec2 },
+}
+
+/// one step of a path: the action, and the relative tile position it ends at
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NavStep {
+    pub action: NavAction,
+    pub to: (i32, i32),
+}
+
+#[derive(Copy, Clone, Debug)]
+struct NavEdge {
+    step: NavStep,
+    cost: f32,
+}
+
+/// how fast a character moves, which decides how far it can jump
+#[derive(Copy, Clone, Debug)]
+pub struct Movement {
+    pub run_speed: f32,
+    pub jump_speed: f32,
+}
+
+impl Default for Movement {
+    /// the player's
+    fn default() -> Self {
+        Self {
+            run_speed: PLAYER_RUN_SPEED,
+            jump_speed: Player::default().jump_speed,
+        }
+    }
+}
+
+/// where a character a tile in size can stand or hang in a map, and how it can get between them
+///
+/// the graph is an approximation: characters are treated as points at the center of tiles, and
+/// jumps are checked along a single arc.
+#[derive(Resource)]
+pub struct NavGraph {
+    pub movement: Movement,
+    edges: HashMap<(i32, i32), Vec<NavEdge>>,
+}
+
+fn passable(map: &Map, x: i32, y: i32) -> bool {
+    map.in_bounds(x, y) && !map.is_obstacle(x, y) && !map.is_hazard(x, y)
+}
+
+/// on something, rather than falling or hanging on a ladder
+fn standing(map: &Map, x: i32, y: i32) -> bool {
+    passable(map, x, y)
+        && (map.is_ground(x, y - 1)
+            || map.is_platform(x, y - 1)
+            || (map.is_ladder(x, y - 1) && !map.is_ladder(x, y)))
+}
+
+/// the row a character falling from a tile lands on, `None` if it falls out of the map or into
+/// a hazard
+fn land(map: &Map, x: i32, y: i32) -> Option<i32> {
+    for y in (0..=y).rev() {
+        if !passable(map, x, y) {
+            return None;
+        }
+        if standing(map, x, y) {
+            return Some(y);
+        }
+    }
+    None
+}
+
+impl NavGraph {
+    pub fn new(map: &Map, movement: Movement) -> Self {
+        let mut graph = Self {
+            movement,
+            edges: HashMap::new(),
+        };
+        for y in 0..map.height {
+            for x in 0..map.width {
+                if standing(map, x, y) || (passable(map, x, y) && map.is_ladder(x, y)) {
+                    let edges = graph.edges_from(map, x, y);
+                    graph.edges.insert((x, y), edges);
+                }
+            }
+        }
+        graph
+    }
+
+    fn edges_from(&self, map: &Map, x: i32, y: i32) -> Vec<NavEdge> {
+        let mut edges = Vec::new();
+        let mut add = |action: NavAction, to: (i32, i32), factor: f32| {
+            let length = Vec2::new((to.0 - x) as f32, (to.1 - y) as f32).length();
+            edges.push(NavEdge {
+                step: NavStep { action, to },
+                cost: length * factor,
+            });
+        };
+
+        // climbing, including off the top of a ladder and onto one from above
+        if map.is_ladder(x, y) && (standing(map, x, y + 1) || map.is_ladder(x, y + 1)) {
+            add(NavAction::Climb, (x, y + 1), 2.);
+        }
+        if map.is_ladder(x, y - 1) && passable(map, x, y - 1) {
+            add(NavAction::Climb, (x, y - 1), 2.);
+        }
+
+        // the rest need something to stand on
+        if !standing(map, x, y) {
+            return edges;
+        }
+
+        for dx in [-1, 1] {
+            if standing(map, x + dx, y) {
+                add(NavAction::Walk, (x + dx, y), 1.);
+            } else if passable(map, x + dx, y) {
+                if let Some(to_y) = land(map, x + dx, y) {
+                    add(NavAction::Fall, (x + dx, to_y), 1.);
+                }
+            }
+        }
+
+        if map.is_platform(x, y - 1) {
+            if let Some(to_y) = land(map, x, y - 1) {
+                add(NavAction::Drop, (x, to_y), 1.);
+            }
+        }
+
+        for (to, speed) in self.jumps(map, x, y) {
+            // a little extra, so walking is preferred
+            let length = Vec2::new((to.0 - x) as f32, (to.1 - y) as f32).length();
+            add(NavAction::Jump { speed }, to, (length + 1.) / length);
+        }
+        edges
+    }
+
+    /// tiles reachable with a single jump, and the speed to jump at
+    fn jumps(&self, map: &Map, x: i32, y: i32) -> Vec<((i32, i32), Vec2)> {
+        let tile_size = map.tile_size as f32;
+        let gravity = -GRAVITY;
+        let jump_speed = self.movement.jump_speed;
+        let max_height = (jump_speed * jump_speed / (2. * gravity) / tile_size) as i32;
+        // lower than this and it's easier to fall
+        let max_depth = max_height + 2;
+
+        let mut jumps = Vec::new();
+        for dy in -max_depth..=max_height {
+            // time until coming back down to the height of the target
+            let height = dy as f32 * tile_size;
+            let time =
+                (jump_speed + (jump_speed * jump_speed - 2. * gravity * height).sqrt()) / gravity;
+            let reach = (self.movement.run_speed * time / tile_size) as i32;
+            for dx in -reach..=reach {
+                // next to it, or straight down, is walking and falling
+                if (dx.abs() <= 1 && dy <= 0) || (dx, dy) == (0, 0) {
+                    continue;
+                }
+                let to = (x + dx, y + dy);
+                if !standing(map, to.0, to.1) {
+                    continue;
+                }
+                let speed = Vec2::new(dx as f32 * tile_size / time, jump_speed);
+                if self.arc_clear(map, (x, y), to, speed, time) {
+                    jumps.push((to, speed));
+                }
+            }
+        }
+        jumps
+    }
+
+    /// whether a jump can get from one tile to another without hitting anything
+    ///
+    /// above the map is open, like it is for the physics.
+    fn arc_clear(
+        &self,
+        map: &Map,
+        from: (i32, i32),
+        to: (i32, i32),
+        speed: Vec2,
+        time: f32,
+    ) -> bool {
+        let tile_size = map.tile_size as f32;
+        // a sample every few pixels along the way
+        let steps = ((speed.x.abs() + speed.y) * time / 4.).ceil().max(1.) as i32;
+        (1..steps).all(|i| {
+            let t = time * i as f32 / steps as f32;
+            let offset = Vec2::new(speed.x * t, speed.y * t + GRAVITY * t * t / 2.) / tile_size;
+            let x = (from.0 as f32 + offset.x).round() as i32;
+            let y = (from.1 as f32 + offset.y).round() as i32;
+            (x, y) == to || passable(map, x, y) || (y >= map.height && x >= 0 && x < map.width)
+        })
+    }
+
+    /// whether a character can be at a tile
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        self.edges.contains_key(&(x, y))
+    }
+
+    /// the cheapest way from one tile to another, `None` if there isn't one
+    pub fn find_path(&self, from: (i32, i32), to: (i32, i32)) -> Option<Vec<NavStep>> {
+        if !self.edges.contains_key(&from) || !self.edges.contains_key(&to) {
+            return None;
+        }
+        let estimate = |pos: (i32, i32)| {
+            // the cost of an edge is never less than its length
+            Vec2::new((to.0 - pos.0) as f32, (to.1 - pos.1) as f32).length()
+        };
+
+        let mut open = BinaryHeap::new();
+        let mut costs: HashMap<(i32, i32), f32> = HashMap::new();
+        let mut came_from: HashMap<(i32, i32), ((i32, i32), NavAction)> = HashMap::new();
+        costs.insert(from, 0.);
+        open.push(Candidate {
+            estimate: estimate(from),
+            pos: from,
+        });
+
+        while let Some(Candidate { pos, .. }) = open.pop() {
+            if pos == to {
+                let mut path = Vec::new();
+                let mut pos = to;
+                while let Some((prev, action)) = came_from.get(&pos) {
+                    path.push(NavStep {
+                        action: *action,
+                        to: pos,
+                    });
+                    pos = *prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let cost = costs[&pos];
+            for edge in self.edges[&pos].iter() {
+                let next = edge.step.to;
+                let next_cost = cost + edge.cost;
+                if !costs.get(&next).is_some_and(|c| *c <= next_cost) {
+                    costs.insert(next, next_cost);
+                    came_from.insert(next, (pos, edge.step.action));
+                    open.push(Candidate {
+                        estimate: next_cost + estimate(next),
+                        pos: next,
+                    });
+                }
+            }
+        }
+        None
+    }
+}
+
+/// a tile to look at next, cheapest first
+struct Candidate {
+    estimate: f32,
+    pos: (i32, i32),
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed, since the heap pops the largest
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+/// rebuild the navigation graph whenever the map changes
+pub fn rebuild_nav_graph(map: Res<Map>, mut graph: ResMut<NavGraph>) {
+    if map.is_changed() {
+        *graph = NavGraph::new(&map, graph.movement);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(ascii: &str) -> NavGraph {
+        NavGraph::new(&Map::from_ascii(ascii), Movement::default())
+    }
+
+    fn actions(path: &[NavStep]) -> Vec<NavAction> {
+        path.iter()
+            .map(|s| match s.action {
+                // the exact speed doesn't matter here
+                NavAction::Jump { .. } => NavAction::Jump { speed: Vec2::ZERO },
+                action => action,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn walk() {
+        let graph = graph(
+            "
+            ----
+            ####
+            ",
+        );
+        assert!(graph.contains(0, 1));
+        assert!(!graph.contains(0, 0));
+        let path = graph.find_path((0, 1), (3, 1)).unwrap();
+        assert_eq!(actions(&path), vec![NavAction::Walk; 3]);
+        assert_eq!(path.last().unwrap().to, (3, 1));
+        assert_eq!(graph.find_path((2, 1), (2, 1)), Some(vec![]));
+        assert_eq!(graph.find_path((0, 1), (0, 0)), None);
+    }
+
+    #[test]
+    fn jump_gaps() {
+        let graph = graph(
+            "
+            ------------------
+            ------------------
+            ##--##---------###
+            ",
+        );
+        let path = graph.find_path((1, 1), (4, 1)).unwrap();
+        assert_eq!(path.len(), 1);
+        match path[0].action {
+            NavAction::Jump { speed } => {
+                assert!(speed.x > 0. && speed.x <= graph.movement.run_speed);
+                assert_eq!(speed.y, graph.movement.jump_speed);
+            }
+            _ => panic!("expected a jump, not {:?}", path[0].action),
+        }
+        assert!(graph.find_path((0, 1), (5, 1)).is_some());
+        // too far to jump
+        assert_eq!(graph.find_path((5, 1), (15, 1)), None);
+    }
+
+    #[test]
+    fn ladders_and_platforms() {
+        let graph = graph(
+            "
+            ------
+            |=====
+            |-----
+            |-----
+            ######
+            ",
+        );
+        // too high to jump, so up the ladder
+        let path = graph.find_path((3, 1), (3, 4)).unwrap();
+        assert_eq!(
+            actions(&path),
+            vec![
+                NavAction::Walk,
+                NavAction::Walk,
+                NavAction::Walk,
+                NavAction::Climb,
+                NavAction::Climb,
+                NavAction::Climb,
+                NavAction::Walk,
+                NavAction::Walk,
+                NavAction::Walk,
+            ]
+        );
+        // and straight through the platform on the way down
+        let path = graph.find_path((3, 4), (3, 1)).unwrap();
+        assert_eq!(
+            path,
+            vec![NavStep {
+                action: NavAction::Drop,
+                to: (3, 1)
+            }]
+        );
+    }
+
+    #[test]
+    fn ledges() {
+        let graph = graph(
+            "
+            ------
+            ##----
+            ------
+            ######
+            ",
+        );
+        let path = graph.find_path((1, 3), (4, 1)).unwrap();
+        assert_eq!(
+            path[0],
+            NavStep {
+                action: NavAction::Fall,
+                to: (2, 1)
+            }
+        );
+        // back up needs a jump
+        let path = graph.find_path((4, 1), (1, 3)).unwrap();
+        assert!(matches!(
+            path.last().unwrap().action,
+            NavAction::Jump { .. }
+        ));
+    }
+
+    #[test]
+    fn hazards() {
+        let graph = graph(
+            "
+            ----------
+            --^^^^^^--
+            ##------##
+            ",
+        );
+        // falling off the ledge lands in lava, and it's too far to jump over
+        assert!(!graph.contains(3, 1));
+        assert_eq!(graph.find_path((0, 1), (9, 1)), None);
+    }
+}
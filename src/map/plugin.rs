@@ -1,112 +1,272 @@
-use bevy::prelude::*;
+use std::collections::HashSet;
 
-use crate::components::{Collider, Tile};
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::components::{Collider, Tile, TilePos};
 
 use super::{
-    events::tile_collision_listener,
-    generator::{build_default_map, TileType},
-    Map,
+    animation::{animate_tiles, AnimatedTile},
+    background::{scroll_background, spawn_background},
+    chunks::{stream_chunks, ChunkPos, LoadedChunks},
+    colliders::{bake_colliders, TileRect},
+    destructible::{break_tiles, debris_movement, reset_tile_damage, TileDamage},
+    doors::{pressure_plates, toggle_switches, unlock_doors, use_switches},
+    events::{
+        tile_collision_listener, LevelLoaded, MapReloaded, TerrainDamage, TilesChanged,
+        ToggleSwitch,
+    },
+    generator::TileType,
+    hazards::hazard_damage,
+    levels::{
+        build_default_campaign, level_exit, level_transition, setup_transition_overlay,
+        LevelTransition,
+    },
+    navigation::rebuild_nav_graph,
+    reload::{watch_map_file, MapWatcher},
+    tileset::{build_default_tileset, Tileset},
+    Map, Movement, NavGraph,
 };
 
 pub struct MapPlugin;
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
-        let map = build_default_map();
-        app.insert_resource(map)
-            .add_startup_stage("map render", SystemStage::single(map_render))
-            .add_system(tile_collision_listener);
+        let campaign = build_default_campaign();
+        let map = campaign.current_level().to_map();
+        app.insert_resource(NavGraph::new(&map, Movement::default()))
+            .insert_resource(map)
+            .insert_resource(campaign)
+            .insert_resource(build_default_tileset())
+            .init_resource::<LevelTransition>()
+            .init_resource::<MapWatcher>()
+            .init_resource::<LoadedChunks>()
+            .init_resource::<TileDamage>()
+            .add_event::<LevelLoaded>()
+            .add_event::<MapReloaded>()
+            .add_event::<TilesChanged>()
+            .add_event::<TerrainDamage>()
+            .add_event::<ToggleSwitch>()
+            .add_startup_system(setup_transition_overlay)
+            .add_startup_system(setup_colliders)
+            .add_system_to_stage(CoreStage::PreUpdate, stream_chunks)
+            .add_system(tile_collision_listener)
+            .add_system(animate_tiles)
+            .add_system(hazard_damage)
+            .add_system(level_exit)
+            .add_system(level_transition.after(level_exit))
+            .add_system(watch_map_file)
+            .add_system(break_tiles)
+            .add_system(debris_movement)
+            .add_system(reset_tile_damage.before(break_tiles))
+            .add_system(use_switches)
+            .add_system(toggle_switches.after(use_switches))
+            .add_system(pressure_plates)
+            .add_system(unlock_doors)
+            .add_system(rebuild_nav_graph)
+            .add_system(spawn_background)
+            .add_system(scroll_background.after(spawn_background));
+    }
+}
+
+/// spawn the tile sprites for a chunk
+pub fn spawn_chunk(
+    commands: &mut Commands,
+    map: &Map,
+    tileset: &Tileset,
+    asset_server: &AssetServer,
+    chunk: ChunkPos,
+) {
+    let area = chunk.area(map);
+    for tile_y in area.y0..=area.y1 {
+        for tile_x in area.x0..=area.x1 {
+            spawn_tile(commands, map, tileset, asset_server, tile_x, tile_y);
+        }
     }
 }
 
-fn map_render(mut commands: Commands, map: Res<Map>, asset_server: Res<AssetServer>) {
-    for (i, tile) in map
-        .tiles
-        .iter()
-        .enumerate()
-        .filter(|x| *x.1 != TileType::Empty)
-    {
-        let mut tile_size = Vec2::new(32., 32.);
-        let mut half_tile_size = tile_size / 2.0;
-        let pos = map.tile_position(i as i32 % map.width, i as i32 / map.width);
-
-        let mut collider: Option<Collider> = Some(Collider::from_position(
-            Vec3::from((pos, 0.)),
-            half_tile_size,
+/// spawn the sprite for a single tile, if it isn't empty
+pub fn spawn_tile(
+    commands: &mut Commands,
+    map: &Map,
+    tileset: &Tileset,
+    asset_server: &AssetServer,
+    tile_x: i32,
+    tile_y: i32,
+) {
+    let tile = map.tile(tile_x, tile_y);
+    if tile == TileType::Empty {
+        return;
+    }
+    let sprite = match tileset.sprite(map, tile_x, tile_y) {
+        Some(sprite) => sprite,
+        None => {
+            println!("No sprite in tileset for {:?}", tile);
+            return;
+        }
+    };
+    let (offset_x, offset_y) = sprite.offset.unwrap_or_default();
+    let pos = map.tile_position(tile_x, tile_y) + Vec2::new(offset_x, offset_y);
+
+    let mut entity = commands.spawn(SpriteBundle {
+        texture: asset_server.load(sprite.texture.as_str()),
+        sprite: sprite.sprite(),
+        transform: Transform {
+            translation: Vec3::new(pos.x, pos.y, 1.),
+            scale: Vec3::new(sprite.scale, sprite.scale, 1.),
+            ..default()
+        },
+        ..default()
+    });
+
+    entity
+        .insert(Tile)
+        .insert(TilePos {
+            x: tile_x,
+            y: tile_y,
+        })
+        .insert(ChunkPos::of_tile(tile_x, tile_y));
+
+    if let Some(animation) = &sprite.animation {
+        entity.insert(AnimatedTile {
+            animation: animation.clone(),
+        });
+    }
+}
+
+/// spawn the colliders for the whole map
+///
+/// unlike the sprites, colliders aren't streamed with the chunks. runs of tiles are merged
+/// across chunk borders, so there are few of them, and bodies away from the camera keep
+/// standing on the ground.
+pub fn spawn_colliders(commands: &mut Commands, map: &Map) {
+    let area = TileRect {
+        x0: 0,
+        y0: 0,
+        x1: map.width - 1,
+        y1: map.height - 1,
+    };
+    for collider in bake_colliders(map, area) {
+        commands.spawn((
+            Tile,
+            collider,
+            TransformBundle::from_transform(Transform::from_xyz(
+                collider.pos.x,
+                collider.pos.y,
+                1.,
+            )),
         ));
-        let mut sprite_scale = 2.0;
+    }
+}
 
-        let texture = match &tile {
-            TileType::Lava => {
-                sprite_scale = 1.0;
-                Some(asset_server.load("lava_01.png"))
-            }
-            TileType::Ladder => {
-                collider = None;
-                Some(asset_server.load("ladder.png"))
+fn setup_colliders(mut commands: Commands, map: Res<Map>) {
+    spawn_colliders(&mut commands, &map);
+}
+
+/// updates the spawned tiles and colliders when the map changes
+#[allow(clippy::type_complexity)]
+#[derive(SystemParam)]
+pub struct MapSpawner<'w, 's> {
+    commands: Commands<'w, 's>,
+    tileset: Res<'w, Tileset>,
+    asset_server: Res<'w, AssetServer>,
+    loaded: ResMut<'w, LoadedChunks>,
+    ev_reloaded: EventWriter<'w, 's, MapReloaded>,
+    ev_changed: EventWriter<'w, 's, TilesChanged>,
+    tile_query: Query<'w, 's, (Entity, &'static TilePos)>,
+    chunk_query: Query<'w, 's, (Entity, &'static ChunkPos)>,
+    collider_query: Query<'w, 's, Entity, (With<Tile>, With<Collider>)>,
+}
+
+impl<'w, 's> MapSpawner<'w, 's> {
+    /// replace the whole map, respawning the chunks which were loaded
+    pub fn load(&mut self, map: &mut Map, new_map: Map) {
+        for (entity, _) in self.chunk_query.iter() {
+            self.commands.entity(entity).despawn_recursive();
+        }
+        *map = new_map;
+        self.respawn_colliders(map);
+
+        // respawn straight away, so a camera that didn't move still sees the map
+        let chunks: Vec<ChunkPos> = self.loaded.chunks.drain().collect();
+        for chunk in chunks {
+            if map.has_chunk(chunk) {
+                spawn_chunk(
+                    &mut self.commands,
+                    map,
+                    &self.tileset,
+                    &self.asset_server,
+                    chunk,
+                );
+                self.loaded.chunks.insert(chunk);
             }
-            TileType::Empty => None,
-            TileType::Platform => {
-                tile_size = Vec2::new(32., 12.);
-                half_tile_size = tile_size / 2.;
-                collider = Some(Collider {
-                    pos,
-                    half: Vec2::new(half_tile_size.x, 0.5),
-                    top: true,
-                    left: true,
-                    right: true,
-                    bottom: false,
-                });
-                Some(asset_server.load("edge.png"))
+        }
+    }
+
+    /// replace the map after the level file changed, see `replace`
+    pub fn reload(&mut self, map: &mut Map, new_map: Map) {
+        self.replace(map, new_map);
+        self.ev_reloaded.send(MapReloaded);
+    }
+
+    /// replace the map, only respawning the tiles which changed (and their neighbours)
+    pub fn replace(&mut self, map: &mut Map, new_map: Map) {
+        match map.diff(&new_map) {
+            // the size changed, so every tile moved
+            None => {
+                self.load(map, new_map);
+                let mut tiles = Vec::new();
+                for y in 0..map.height {
+                    for x in 0..map.width {
+                        tiles.push((x, y));
+                    }
+                }
+                self.ev_changed.send(TilesChanged { tiles });
             }
-            TileType::Solid => {
-                collider = Some(Collider::from_position(
-                    Vec3::from((pos, 0.)),
-                    half_tile_size,
-                ));
-                // previous tile was same row and a solid?
-                if (i - 1) / map.width as usize == i / map.width as usize
-                    && map.tiles[i - 1] == TileType::Solid
-                {
-                    // current tile is not end of row or next tile is solid
-                    if i as i32 % map.width != map.width - 1 as i32
-                        && map.tiles[i + 1] == TileType::Solid
-                    {
-                        Some(asset_server.load("wall_mid.png"))
-                    } else {
-                        Some(asset_server.load("wall_right.png"))
+            Some(changed) => {
+                *map = new_map;
+
+                // neighbours of changed tiles may need a different sprite
+                let mut dirty = HashSet::new();
+                for (x, y) in changed.iter().copied() {
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            if map.in_bounds(x + dx, y + dy) {
+                                dirty.insert((x + dx, y + dy));
+                            }
+                        }
                     }
-                // previous tile was air or first tile in row
-                } else if (i - 1) / 32 != i / 32 || map.tiles[i - 1] != TileType::Solid {
-                    // current tile is end of row
-                    if i as i32 % map.width == map.width - 1 as i32 {
-                        Some(asset_server.load("wall_mid.png"))
-                    // next tile is air
-                    } else if map.tiles[i + 1] != TileType::Solid {
-                        Some(asset_server.load("wall_mid.png"))
-                    } else {
-                        Some(asset_server.load("wall_left.png"))
+                }
+                let loaded =
+                    |(x, y): &(i32, i32)| self.loaded.chunks.contains(&ChunkPos::of_tile(*x, *y));
+                dirty.retain(loaded);
+
+                for (entity, pos) in self.tile_query.iter() {
+                    if dirty.contains(&(pos.x, pos.y)) {
+                        self.commands.entity(entity).despawn_recursive();
                     }
-                } else {
-                    Some(asset_server.load("wall_mid.png"))
                 }
-            }
-        };
-
-        let mut entity = commands.spawn(SpriteBundle {
-            texture: texture.unwrap(),
-            transform: Transform {
-                translation: Vec3::new(pos.x, pos.y, 1.),
-                scale: Vec3::new(sprite_scale, sprite_scale, 1.),
-                ..default()
-            },
-            ..default()
-        });
+                for (x, y) in dirty.iter() {
+                    spawn_tile(
+                        &mut self.commands,
+                        map,
+                        &self.tileset,
+                        &self.asset_server,
+                        *x,
+                        *y,
+                    );
+                }
 
-        entity.insert(Tile);
+                self.respawn_colliders(map);
+                self.ev_changed.send(TilesChanged { tiles: changed });
+            }
+        }
+    }
 
-        if let Some(c) = collider {
-            entity.insert(c);
+    /// colliders are merged across the whole map, so any change can affect them
+    fn respawn_colliders(&mut self, map: &Map) {
+        for entity in self.collider_query.iter() {
+            self.commands.entity(entity).despawn_recursive();
         }
+        spawn_colliders(&mut self.commands, map);
     }
 }
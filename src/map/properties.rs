@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::Map;
+
+/// a value attached to a tile, written in level files as a plain ron value
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f32),
+    String(String),
+}
+
+impl PropertyValue {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            PropertyValue::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            PropertyValue::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// ints are converted, so `1` and `1.0` are both read as a float
+    pub fn as_float(&self) -> Option<f32> {
+        match self {
+            PropertyValue::Float(v) => Some(*v),
+            PropertyValue::Int(v) => Some(*v as f32),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PropertyValue::String(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+pub type TileProperties = HashMap<String, PropertyValue>;
+
+impl Map {
+    /// the properties of the tile at relative position, if it has any
+    pub fn properties(&self, x: i32, y: i32) -> Option<&TileProperties> {
+        self.properties.get(&(x, y))
+    }
+
+    pub fn property(&self, x: i32, y: i32, key: &str) -> Option<&PropertyValue> {
+        self.properties(x, y)?.get(key)
+    }
+
+    /// tiles outside of the map can't have properties
+    pub fn set_property(&mut self, x: i32, y: i32, key: &str, value: PropertyValue) {
+        if self.in_bounds(x, y) {
+            self.properties
+                .entry((x, y))
+                .or_default()
+                .insert(key.to_string(), value);
+        }
+    }
+
+    #[allow(unused)]
+    pub fn remove_property(&mut self, x: i32, y: i32, key: &str) -> Option<PropertyValue> {
+        let properties = self.properties.get_mut(&(x, y))?;
+        let value = properties.remove(key);
+        if properties.is_empty() {
+            self.properties.remove(&(x, y));
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_and_remove() {
+        let mut map = Map::new(4, 4);
+        map.set_property(1, 2, "damage", PropertyValue::Int(10));
+        map.set_property(1, 2, "door", PropertyValue::String("red".into()));
+        map.set_property(10, 10, "damage", PropertyValue::Int(10));
+
+        assert_eq!(map.property(1, 2, "damage").unwrap().as_int(), Some(10));
+        assert_eq!(map.property(1, 2, "door").unwrap().as_str(), Some("red"));
+        assert!(map.property(2, 1, "damage").is_none());
+        assert!(map.properties(10, 10).is_none());
+
+        assert_eq!(
+            map.remove_property(1, 2, "damage"),
+            Some(PropertyValue::Int(10))
+        );
+        assert!(map.properties(1, 2).is_some());
+        map.remove_property(1, 2, "door");
+        assert!(map.properties.is_empty());
+    }
+
+    #[test]
+    fn parse_values() {
+        let properties: TileProperties =
+            ron::from_str(r#"{ "a": true, "b": 5, "c": 0.5, "d": "red", "e": -3 }"#).unwrap();
+        assert_eq!(properties["a"], PropertyValue::Bool(true));
+        assert_eq!(properties["b"], PropertyValue::Int(5));
+        assert_eq!(properties["c"], PropertyValue::Float(0.5));
+        assert_eq!(properties["d"], PropertyValue::String("red".into()));
+        assert_eq!(properties["e"], PropertyValue::Int(-3));
+
+        for value in properties.values() {
+            let parsed: PropertyValue = ron::from_str(&ron::to_string(value).unwrap()).unwrap();
+            assert_eq!(&parsed, value);
+        }
+        assert_eq!(ron::to_string(&PropertyValue::Float(2.)).unwrap(), "2.0");
+    }
+
+    #[test]
+    fn typed_accessors() {
+        assert_eq!(PropertyValue::Bool(true).as_bool(), Some(true));
+        assert_eq!(PropertyValue::Int(1).as_bool(), None);
+        assert_eq!(PropertyValue::Int(-3).as_int(), Some(-3));
+        assert_eq!(PropertyValue::Float(2.).as_int(), None);
+        assert_eq!(PropertyValue::Float(0.5).as_float(), Some(0.5));
+        assert_eq!(PropertyValue::Int(2).as_float(), Some(2.));
+        assert_eq!(PropertyValue::String("2".into()).as_float(), None);
+        assert_eq!(PropertyValue::String("red".into()).as_str(), Some("red"));
+        assert_eq!(PropertyValue::Bool(false).as_str(), None);
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::SystemTime;
+
+use bevy::prelude::*;
+
+use crate::assets::asset_path;
+
+use super::{
+    levels::{Campaign, Level, LevelTransition, TransitionState},
+    plugin::MapSpawner,
+    Map,
+};
+
+// seconds between checks of the level file
+const WATCH_INTERVAL: f32 = 0.5;
+
+/// tracks the modification time of the current level file
+#[derive(Resource)]
+pub struct MapWatcher {
+    pub level: usize,
+    pub modified: Option<SystemTime>,
+    pub timer: Timer,
+}
+
+impl Default for MapWatcher {
+    fn default() -> Self {
+        Self {
+            level: 0,
+            modified: None,
+            timer: Timer::from_seconds(WATCH_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(asset_path(path))
+        .and_then(|m| m.modified())
+        .ok()
+}
+
+/// reload the current level when its file changes on disk, only respawning what changed
+pub fn watch_map_file(
+    time: Res<Time>,
+    mut watcher: ResMut<MapWatcher>,
+    campaign: Res<Campaign>,
+    transition: Res<LevelTransition>,
+    mut map: ResMut<Map>,
+    mut spawner: MapSpawner,
+) {
+    if !watcher.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    if transition.state != TransitionState::Idle {
+        return;
+    }
+
+    let path = campaign.current_path();
+    let last_modified = modified(path);
+    // a different level was loaded since we last looked, start watching that one
+    if watcher.level != campaign.current || watcher.modified.is_none() {
+        watcher.level = campaign.current;
+        watcher.modified = last_modified;
+        return;
+    }
+    if last_modified == watcher.modified {
+        return;
+    }
+    watcher.modified = last_modified;
+
+    let new_map = match Level::try_load(path) {
+        Ok(level) => level.to_map(),
+        Err(e) => {
+            println!("Not reloading level: {}", e);
+            return;
+        }
+    };
+
+    println!("Reloading {}", path);
+    spawner.reload(&mut map, new_map);
+}
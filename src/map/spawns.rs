@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum EnemyKind {
+    Slime,
+    Skeleton,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum PickupKind {
+    Health,
+    Coin,
+    /// unlocks one locked door
+    Key,
+}
+
+/// something other than terrain placed in a map, see `Legend`
+///
+/// exits are tiles (`E`) rather than entities, so they aren't in here
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum SpawnKind {
+    Crate,
+    Enemy(EnemyKind),
+    Pickup(PickupKind),
+    Checkpoint,
+    Torch,
+}
+
+impl SpawnKind {
+    pub const ALL: [SpawnKind; 8] = [
+        SpawnKind::Crate,
+        SpawnKind::Enemy(EnemyKind::Slime),
+        SpawnKind::Enemy(EnemyKind::Skeleton),
+        SpawnKind::Pickup(PickupKind::Health),
+        SpawnKind::Pickup(PickupKind::Coin),
+        SpawnKind::Pickup(PickupKind::Key),
+        SpawnKind::Checkpoint,
+        SpawnKind::Torch,
+    ];
+
+    /// the character used for this kind in the default legend
+    pub fn marker(&self) -> char {
+        match self {
+            SpawnKind::Crate => 'C',
+            SpawnKind::Enemy(EnemyKind::Slime) => 'S',
+            SpawnKind::Enemy(EnemyKind::Skeleton) => 'K',
+            SpawnKind::Pickup(PickupKind::Health) => 'H',
+            SpawnKind::Pickup(PickupKind::Coin) => '$',
+            SpawnKind::Pickup(PickupKind::Key) => 'k',
+            SpawnKind::Checkpoint => 'P',
+            SpawnKind::Torch => 'T',
+        }
+    }
+}
+
+/// an entity to spawn, at a relative tile position
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EntitySpawn {
+    pub kind: SpawnKind,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// the characters of a prefab which are entities rather than tiles
+///
+/// the tile under an entity marker is empty.
+#[derive(Clone, Debug)]
+pub struct Legend {
+    pub markers: HashMap<char, SpawnKind>,
+}
+
+impl Default for Legend {
+    fn default() -> Self {
+        Self {
+            markers: SpawnKind::ALL.iter().map(|k| (k.marker(), *k)).collect(),
+        }
+    }
+}
+
+impl Legend {
+    /// the default legend, plus `markers`
+    ///
+    /// the default markers can't be replaced, so maps can always be written back out with
+    /// `Map::to_prefab` (which only uses the default markers) and read the same.
+    pub fn with_markers(markers: &HashMap<char, SpawnKind>) -> Self {
+        let mut legend = Self::default();
+        for (c, kind) in markers.iter() {
+            legend.markers.entry(*c).or_insert(*kind);
+        }
+        legend
+    }
+
+    pub fn get(&self, c: char) -> Option<SpawnKind> {
+        self.markers.get(&c).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Map;
+
+    #[test]
+    fn default_legend() {
+        let legend = Legend::default();
+        for kind in SpawnKind::ALL.iter() {
+            assert_eq!(legend.get(kind.marker()), Some(*kind));
+        }
+        assert_eq!(legend.markers.len(), SpawnKind::ALL.len());
+        assert!(legend.get('#').is_none());
+    }
+
+    #[test]
+    fn prefab_markers() {
+        let map = Map::from_ascii(
+            "
+            -S-$
+            C##P
+            ",
+        );
+        assert!(map.is_empty(0, 0));
+        assert!(map.is_empty(3, 1));
+        assert_eq!(
+            map.spawns,
+            vec![
+                EntitySpawn {
+                    kind: SpawnKind::Enemy(EnemyKind::Slime),
+                    x: 1,
+                    y: 1
+                },
+                EntitySpawn {
+                    kind: SpawnKind::Pickup(PickupKind::Coin),
+                    x: 3,
+                    y: 1
+                },
+                EntitySpawn {
+                    kind: SpawnKind::Crate,
+                    x: 0,
+                    y: 0
+                },
+                EntitySpawn {
+                    kind: SpawnKind::Checkpoint,
+                    x: 3,
+                    y: 0
+                },
+            ]
+        );
+        assert_eq!(map.to_prefab(), "-S-$\nC##P\n");
+    }
+
+    #[test]
+    fn custom_legend() {
+        let markers: HashMap<char, SpawnKind> =
+            ron::from_str("{ 'c': Pickup(Coin), 'S': Enemy(Skeleton) }").unwrap();
+        let legend = Legend::with_markers(&markers);
+        assert_eq!(legend.get('c'), Some(SpawnKind::Pickup(PickupKind::Coin)));
+        assert_eq!(legend.get('S'), Some(SpawnKind::Enemy(EnemyKind::Slime)));
+        assert_eq!(legend.get('C'), Some(SpawnKind::Crate));
+
+        let map = Map::from_ascii_with_legend("c-S", &legend);
+        assert_eq!(map.spawns.len(), 2);
+        // written back with the default legend
+        assert_eq!(map.to_prefab(), "$-S\n");
+    }
+}
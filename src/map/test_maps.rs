@@ -0,0 +1,34 @@
+//! fixed maps for tests that should hold for any map
+
+/// ascii prefabs made of `chars`, top row first and every row ending with a newline
+///
+/// covers a single tile of each character, a single row and column of all of them, every pair
+/// of characters next to and on top of each other, and a map spanning several chunks which
+/// doesn't end on a chunk boundary.
+pub fn edge_cases(chars: &[char]) -> Vec<String> {
+    let grid = |width: usize, height: usize, tile: &dyn Fn(usize, usize) -> char| -> String {
+        (0..height)
+            .map(|y| {
+                let row: String = (0..width).map(|x| tile(x, y)).collect();
+                row + "\n"
+            })
+            .collect()
+    };
+
+    let mut maps = Vec::new();
+    for c in chars {
+        maps.push(format!("{}\n", c));
+    }
+    maps.push(grid(chars.len(), 1, &|x, _| chars[x]));
+    maps.push(grid(1, chars.len(), &|_, y| chars[y]));
+    for a in chars {
+        for b in chars {
+            maps.push(grid(3, 3, &|x, y| if x < 2 && y < 2 { *a } else { *b }));
+        }
+    }
+    // runs of different lengths in both directions, so tiles are merged across chunks
+    maps.push(grid(37, 35, &|x, y| {
+        chars[(x / 3 * 7 + y / 2 * 5 + x * y % 3) % chars.len()]
+    }));
+    maps
+}
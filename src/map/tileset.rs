@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::assets::asset_path;
+
+use super::{
+    autotile::{neighbour_mask, select, Connectivity, Rule},
+    generator::{Map, TileType},
+};
+
+pub const DEFAULT_TILESET: &str = "tilesets/dungeon.ron";
+
+/// how a single tile is drawn
+#[derive(Clone, Debug, Deserialize)]
+pub struct SpriteDef {
+    pub texture: String,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    #[serde(default)]
+    pub flip_x: bool,
+    #[serde(default)]
+    pub flip_y: bool,
+    /// region of the texture to draw: min x, min y, max x, max y
+    #[serde(default)]
+    pub rect: Option<(f32, f32, f32, f32)>,
+    /// size to draw the (region of the) texture at, before scaling
+    #[serde(default)]
+    pub size: Option<(f32, f32)>,
+    /// tint: red, green, blue, alpha
+    #[serde(default)]
+    pub color: Option<(f32, f32, f32, f32)>,
+    /// moves the sprite away from the center of the tile, in pixels
+    #[serde(default)]
+    pub offset: Option<(f32, f32)>,
+    /// name of an entry in `Tileset::animations`, replaces the texture over time
+    #[serde(default)]
+    pub animation: Option<String>,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+impl SpriteDef {
+    pub fn sprite(&self) -> Sprite {
+        Sprite {
+            flip_x: self.flip_x,
+            flip_y: self.flip_y,
+            rect: self.rect.map(|(x0, y0, x1, y1)| Rect::new(x0, y0, x1, y1)),
+            custom_size: self.size.map(|(w, h)| Vec2::new(w, h)),
+            color: self
+                .color
+                .map(|(r, g, b, a)| Color::rgba(r, g, b, a))
+                .unwrap_or(Color::WHITE),
+            ..default()
+        }
+    }
+}
+
+/// auto-tiling rules for one tile type
+#[derive(Clone, Debug, Deserialize)]
+pub struct TileRules {
+    #[serde(default)]
+    pub connectivity: Connectivity,
+    /// tile types that count as a neighbour, defaults to the tile type itself
+    #[serde(default)]
+    pub connects: Vec<TileType>,
+    #[serde(default)]
+    pub rules: Vec<Rule<SpriteDef>>,
+    /// used when no rule matches
+    pub default: SpriteDef,
+}
+
+/// a sequence of textures shown one after the other, looping
+#[derive(Clone, Debug, Deserialize)]
+pub struct TileAnimation {
+    pub frames: Vec<String>,
+    /// in seconds
+    pub frame_duration: f32,
+}
+
+impl TileAnimation {
+    /// index of the frame to show after `elapsed` seconds
+    pub fn frame_at(&self, elapsed: f32) -> usize {
+        if self.frames.is_empty() || self.frame_duration <= 0. {
+            return 0;
+        }
+        (elapsed / self.frame_duration) as usize % self.frames.len()
+    }
+}
+
+#[derive(Resource, Clone, Debug, Deserialize)]
+pub struct Tileset {
+    pub tiles: HashMap<TileType, TileRules>,
+    #[serde(default)]
+    pub animations: HashMap<String, TileAnimation>,
+}
+
+impl Tileset {
+    pub fn from_str(s: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(s)
+    }
+
+    pub fn load(path: &str) -> Self {
+        let full_path = asset_path(path);
+        let contents = std::fs::read_to_string(&full_path)
+            .unwrap_or_else(|e| panic!("unable to read tileset {:?}: {}", full_path, e));
+        Self::from_str(&contents)
+            .unwrap_or_else(|e| panic!("unable to parse tileset {:?}: {}", full_path, e))
+    }
+
+    /// returns the sprite to draw at x/y, based on its neighbours
+    pub fn sprite(&self, map: &Map, x: i32, y: i32) -> Option<&SpriteDef> {
+        let tile = map.tile(x, y);
+        let def = self.tiles.get(&tile)?;
+        let mask = if def.connects.is_empty() {
+            neighbour_mask(map, x, y, &[tile], def.connectivity)
+        } else {
+            neighbour_mask(map, x, y, &def.connects, def.connectivity)
+        };
+        Some(
+            select(&def.rules, mask)
+                .map(|r| &r.sprite)
+                .unwrap_or(&def.default),
+        )
+    }
+}
+
+pub fn build_default_tileset() -> Tileset {
+    Tileset::load(DEFAULT_TILESET)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_tileset() {
+        let tileset = build_default_tileset();
+        let map = Map::from_prefab((
+            "
+            -===-#
+            -###--
+            ^^^^^^
+            ^^^^^^
+            ",
+            6,
+            4,
+        ));
+
+        assert_eq!(tileset.sprite(&map, 1, 2).unwrap().texture, "wall_left.png");
+        assert_eq!(tileset.sprite(&map, 2, 2).unwrap().texture, "wall_mid.png");
+        assert_eq!(
+            tileset.sprite(&map, 3, 2).unwrap().texture,
+            "wall_right.png"
+        );
+        assert!(tileset.sprite(&map, 0, 3).is_none());
+
+        // outside the map is solid, so this is the underside of a corner
+        let corner = tileset.sprite(&map, 5, 3).unwrap();
+        assert_eq!(corner.texture, "wall_left.png");
+        assert!(corner.flip_y);
+
+        assert!(!tileset.sprite(&map, 1, 3).unwrap().flip_x);
+        assert!(tileset.sprite(&map, 3, 3).unwrap().flip_x);
+
+        let surface = tileset.sprite(&map, 0, 1).unwrap();
+        let body = tileset.sprite(&map, 0, 0).unwrap();
+        assert!(surface.rect.is_none());
+        assert!(body.rect.is_some());
+        assert_eq!(surface.animation.as_deref(), Some("lava"));
+        assert!(tileset.animations.contains_key("lava"));
+    }
+
+    #[test]
+    fn animation_frame_at() {
+        let animation = TileAnimation {
+            frames: vec!["a.png".into(), "b.png".into(), "c.png".into()],
+            frame_duration: 0.25,
+        };
+        assert_eq!(animation.frame_at(0.), 0);
+        assert_eq!(animation.frame_at(0.3), 1);
+        assert_eq!(animation.frame_at(0.5), 2);
+        assert_eq!(animation.frame_at(0.8), 0);
+        assert_eq!(animation.frame_at(10.1), 1);
+    }
+}
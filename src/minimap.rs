@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        texture::ImageSampler,
+    },
+};
+
+use crate::{
+    camera::Screen,
+    components::{Enemy, Player},
+    map::{Map, TileType},
+};
+
+// the largest the minimap gets, in pixels, and the most pixels a tile is drawn with
+const MINIMAP_MAX_SIZE: Vec2 = Vec2::new(240., 120.);
+const MINIMAP_MAX_TILE: f32 = 4.;
+const MINIMAP_MARGIN: f32 = 10.;
+const MARKER_SIZE: f32 = 4.;
+
+/// a map of the whole level in the top right corner, toggled with M
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup_minimap)
+            .add_system(toggle_minimap)
+            .add_system(layout_minimap)
+            .add_system(update_minimap)
+            .add_system(update_minimap_markers.after(update_minimap));
+    }
+}
+
+/// the node showing the map's tiles
+#[derive(Component)]
+struct Minimap;
+
+fn setup_minimap(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    // a pixel per tile, kept sharp when scaled up
+    image.sampler_descriptor = ImageSampler::nearest();
+    commands.spawn((
+        Minimap,
+        ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(MINIMAP_MARGIN),
+                    right: Val::Px(MINIMAP_MARGIN),
+                    ..default()
+                },
+                ..default()
+            },
+            image: UiImage(images.add(image)),
+            // under the level transition overlay
+            z_index: ZIndex::Global(50),
+            ..default()
+        },
+    ));
+}
+
+/// keep the minimap in the corner of the game area rather than the window
+fn layout_minimap(screen: Res<Screen>, mut query: Query<&mut Style, With<Minimap>>) {
+    if !screen.is_changed() || screen.size == UVec2::ZERO {
+        return;
+    }
+    let (before, after) = screen.bars();
+    for mut style in query.iter_mut() {
+        style.position.top = Val::Px(before.y + MINIMAP_MARGIN);
+        style.position.right = Val::Px(after.x + MINIMAP_MARGIN);
+    }
+}
+
+fn toggle_minimap(kb: Res<Input<KeyCode>>, mut query: Query<&mut Style, With<Minimap>>) {
+    if !kb.just_pressed(KeyCode::M) {
+        return;
+    }
+    for mut style in query.iter_mut() {
+        style.display = match style.display {
+            Display::None => Display::Flex,
+            _ => Display::None,
+        };
+    }
+}
+
+fn tile_color(tile: TileType) -> [u8; 4] {
+    match tile {
+        TileType::Empty | TileType::DoorOpen => [0, 0, 0, 160],
+        TileType::Solid => [150, 150, 160, 230],
+        TileType::Breakable => [140, 95, 60, 230],
+        TileType::Platform => [190, 150, 90, 230],
+        TileType::Ladder => [220, 200, 80, 230],
+        TileType::Lava => [240, 80, 20, 230],
+        TileType::Exit => [60, 220, 90, 230],
+        TileType::Door | TileType::LockedDoor => [80, 120, 240, 230],
+        TileType::Switch | TileType::SwitchOn | TileType::Plate => [190, 90, 220, 230],
+    }
+}
+
+/// rgba pixels of the minimap, one per tile, from the top row down
+fn minimap_pixels(map: &Map) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity((map.width * map.height * 4) as usize);
+    for y in (0..map.height).rev() {
+        for x in 0..map.width {
+            pixels.extend_from_slice(&tile_color(map.tile(x, y)));
+        }
+    }
+    pixels
+}
+
+/// size of the minimap on screen, as big as it can be while fitting in `MINIMAP_MAX_SIZE`
+fn minimap_size(map: &Map) -> Vec2 {
+    let tiles = Vec2::new(map.width as f32, map.height as f32).max(Vec2::ONE);
+    let scale = (MINIMAP_MAX_SIZE / tiles)
+        .min_element()
+        .min(MINIMAP_MAX_TILE);
+    tiles * scale
+}
+
+/// redraw the minimap whenever the map changes
+fn update_minimap(
+    map: Res<Map>,
+    mut images: ResMut<Assets<Image>>,
+    mut query: Query<(&UiImage, &mut Style), With<Minimap>>,
+) {
+    if !map.is_changed() {
+        return;
+    }
+    for (image, mut style) in query.iter_mut() {
+        let image = match images.get_mut(&image.0) {
+            Some(image) => image,
+            None => continue,
+        };
+        let extent = Extent3d {
+            width: map.width.max(1) as u32,
+            height: map.height.max(1) as u32,
+            depth_or_array_layers: 1,
+        };
+        if image.texture_descriptor.size != extent {
+            image.resize(extent);
+        }
+        if !map.chunks.is_empty() {
+            image.data = minimap_pixels(&map);
+        }
+
+        let size = minimap_size(&map);
+        style.size = Size::new(Val::Px(size.x), Val::Px(size.y));
+    }
+}
+
+/// position of a point in the world on the minimap, from its top left corner
+fn minimap_position(map: &Map, size: Vec2, point: Vec2) -> Vec2 {
+    let (min, max) = map.bounds();
+    let relative = ((point - min) / (max - min)).clamp(Vec2::ZERO, Vec2::ONE);
+    Vec2::new(relative.x * size.x, (1. - relative.y) * size.y)
+}
+
+/// a dot on the minimap for a player or enemy
+#[derive(Component)]
+struct MinimapMarker;
+
+/// keep a marker on the minimap for every player and enemy
+#[allow(clippy::type_complexity)]
+fn update_minimap_markers(
+    mut commands: Commands,
+    map: Res<Map>,
+    mut markers: Local<HashMap<Entity, Entity>>,
+    minimap_query: Query<Entity, With<Minimap>>,
+    tracked_query: Query<
+        (Entity, &GlobalTransform, Option<&Player>),
+        Or<(With<Player>, With<Enemy>)>,
+    >,
+    mut marker_query: Query<&mut Style, With<MinimapMarker>>,
+) {
+    let minimap = match minimap_query.get_single() {
+        Ok(minimap) => minimap,
+        Err(_) => return,
+    };
+
+    // markers of despawned entities
+    markers.retain(|entity, marker| {
+        let alive = tracked_query.contains(*entity);
+        if !alive {
+            commands.entity(*marker).despawn_recursive();
+        }
+        alive
+    });
+
+    let size = minimap_size(&map);
+    for (entity, transform, player) in tracked_query.iter() {
+        let pos =
+            minimap_position(&map, size, transform.translation().truncate()) - MARKER_SIZE / 2.;
+        let position = UiRect {
+            left: Val::Px(pos.x),
+            top: Val::Px(pos.y),
+            ..default()
+        };
+
+        match markers.get(&entity) {
+            Some(marker) => {
+                if let Ok(mut style) = marker_query.get_mut(*marker) {
+                    style.position = position;
+                }
+            }
+            None => {
+                let color = if player.is_some() {
+                    Color::WHITE
+                } else {
+                    Color::RED
+                };
+                let marker = commands
+                    .spawn((
+                        MinimapMarker,
+                        NodeBundle {
+                            style: Style {
+                                position_type: PositionType::Absolute,
+                                position,
+                                size: Size::new(Val::Px(MARKER_SIZE), Val::Px(MARKER_SIZE)),
+                                ..default()
+                            },
+                            background_color: color.into(),
+                            ..default()
+                        },
+                    ))
+                    .id();
+                commands.entity(minimap).add_child(marker);
+                markers.insert(entity, marker);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels() {
+        let map = Map::from_ascii(
+            "
+            -|
+            #^
+            ",
+        );
+        let pixels = minimap_pixels(&map);
+        assert_eq!(pixels.len(), 4 * 4);
+        // top row first
+        assert_eq!(pixels[0..4], tile_color(TileType::Empty));
+        assert_eq!(pixels[4..8], tile_color(TileType::Ladder));
+        assert_eq!(pixels[8..12], tile_color(TileType::Solid));
+        assert_eq!(pixels[12..16], tile_color(TileType::Lava));
+    }
+
+    #[test]
+    fn fits_on_screen() {
+        // small maps are drawn at most `MINIMAP_MAX_TILE` pixels per tile
+        assert_eq!(minimap_size(&Map::new(10, 5)), Vec2::new(40., 20.));
+        // big ones are shrunk to fit, keeping their shape
+        assert_eq!(minimap_size(&Map::new(480, 40)), Vec2::new(240., 20.));
+        assert_eq!(minimap_size(&Map::new(100, 300)), Vec2::new(40., 120.));
+    }
+
+    #[test]
+    fn positions() {
+        let map = Map::new(10, 5);
+        let size = minimap_size(&map);
+        let top_left = map.tile_position(0, 4) + Vec2::new(-16., 16.);
+        assert_eq!(minimap_position(&map, size, top_left), Vec2::ZERO);
+        let bottom_right = map.tile_position(9, 0) + Vec2::new(16., -16.);
+        assert_eq!(minimap_position(&map, size, bottom_right), size);
+        // off the map is kept on the edge
+        assert_eq!(
+            minimap_position(&map, size, bottom_right + Vec2::new(100., 500.)),
+            Vec2::new(size.x, 0.)
+        );
+    }
+}
@@ -51,6 +51,11 @@ fn detect_collisions(
         let was_at_ceiling = body.at_ceiling;
 
         let mut new_collider = Collider::from_position(body.position, rb_collider.half);
+        let colliders: Vec<&Collider> = collider_query
+            .iter()
+            .filter(|(e, c)| *e != entity && !(body.ignore_platforms && c.is_one_way()))
+            .map(|(_, c)| c)
+            .collect();
 
         // update known knowns based on velocity
         // if body.speed.y < 0. || body.speed.y > 0. {
@@ -67,13 +72,7 @@ fn detect_collisions(
         //     body.at_right_tile = false;
         // }
 
-        let nearest = new_collider.sweep_into(
-            collider_query
-                .iter()
-                .filter(|(e, _)| *e != entity)
-                .map(|(_, c)| c),
-            delta,
-        );
+        let nearest = new_collider.sweep_into(colliders.iter().copied(), delta);
         if let Some(hit) = nearest.hit {
             // TODO: this math isnt precise
             // - sometimes it pushes you up from the ground (vs on top of the collider)
@@ -102,49 +101,25 @@ fn detect_collisions(
 
         if was_at_ceiling && body.at_ceiling {
             let delta = Vec2::new(0., 2.0);
-            let nearest = new_collider.sweep_into(
-                collider_query
-                    .iter()
-                    .filter(|(e, _)| *e != entity)
-                    .map(|(_, c)| c),
-                delta,
-            );
+            let nearest = new_collider.sweep_into(colliders.iter().copied(), delta);
             body.at_ceiling = nearest.hit.is_some();
         }
 
         if was_on_ground && body.on_ground {
             let delta = Vec2::new(0., -1.0);
-            let nearest = new_collider.sweep_into(
-                collider_query
-                    .iter()
-                    .filter(|(e, _)| *e != entity)
-                    .map(|(_, c)| c),
-                delta,
-            );
+            let nearest = new_collider.sweep_into(colliders.iter().copied(), delta);
             body.on_ground = nearest.hit.is_some();
         }
 
         if was_at_right_tile && body.at_right_tile {
             let delta = Vec2::new(2., 0.0);
-            let nearest = new_collider.sweep_into(
-                collider_query
-                    .iter()
-                    .filter(|(e, _)| *e != entity)
-                    .map(|(_, c)| c),
-                delta,
-            );
+            let nearest = new_collider.sweep_into(colliders.iter().copied(), delta);
             body.at_right_tile = nearest.hit.is_some();
         }
 
         if was_at_left_tile && body.at_left_tile {
             let delta = Vec2::new(-2., 0.0);
-            let nearest = new_collider.sweep_into(
-                collider_query
-                    .iter()
-                    .filter(|(e, _)| *e != entity)
-                    .map(|(_, c)| c),
-                delta,
-            );
+            let nearest = new_collider.sweep_into(colliders.iter().copied(), delta);
             body.at_left_tile = nearest.hit.is_some();
         }
 
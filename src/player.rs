@@ -1,28 +1,75 @@
+use std::collections::HashMap;
+
 use bevy::prelude::*;
+use rand::seq::SliceRandom;
 
 use crate::{
+    animation::{Animation, AnimationEvent, AnimationSet, KNIGHT_ANIMATIONS},
+    camera_effects::CameraEffect,
     components::{
-        Collider, Direction, Player, PlayerBundle, PlayerReadyAttack, PlayerState, Projectile,
+        Attacking, Collider, Controls, Direction, Dying, Flash, Health, Invulnerable, Player,
+        PlayerBundle, PlayerReadyAttack, PlayerState, PointLight2d, Projectile, RespawnPoint,
         RigidBody, Speed,
     },
-    constants::{GRAVITY, MAX_FALLING_SPEED, PLATFORM_THRESHOLD, SPRITE_SCALE},
-    map::Map,
-    resources::WinSize,
+    constants::{
+        DEATH_DURATION, FLASH_DURATION, GRAVITY, LANDING_SHAKE_SPEED, MAX_FALLING_SPEED,
+        PLATFORM_THRESHOLD, RESPAWN_INVULNERABILITY, SPRITE_SCALE,
+    },
+    map::{Campaign, LevelLoaded, Map, MapReloaded, SpawnKind, TerrainDamage, ToggleSwitch},
 };
 
+// events sent by the attack animation: when the projectile is fired, and when it's over
+const ATTACK_RELEASE: &str = "attack release";
+const ATTACK_END: &str = "attack end";
+
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_startup_stage("game_setup_actors", SystemStage::single(player_spawn))
+            .add_system(player_join)
             .add_system(player_movement)
+            .add_system(player_landing)
             .add_system(projectile_movement)
-            .add_system(player_attack);
+            .add_system(player_attack)
+            .add_system(player_attack_fallback.before(player_attack_events))
+            .add_system(player_attack_events)
+            .add_system(player_death)
+            .add_system(player_respawn)
+            .add_system(player_invulnerability)
+            .add_system(player_level_start)
+            .add_system(player_map_reloaded);
     }
 }
 
 fn player_spawn(mut commands: Commands, map: Res<Map>, asset_server: Res<AssetServer>) {
-    let spawn_pos = map.starting_positions[0];
+    spawn_player(
+        &mut commands,
+        &asset_server,
+        map.starting_positions[0],
+        Controls::default(),
+    );
+
+    // spawn with default weapon
+    // commands
+    //     .spawn(SpriteBundle {
+    //         material: materials.weapon_bow.clone(),
+    //         transform: Transform {
+    //             translation: Vec3::new(8.0, bottom + 75.0 / 4.0 + 10.0, EQUIPMENT_LAYER),
+    //             scale: Vec3::new(1.5, 1.5, 1.0),
+    //             ..default()
+    //         },
+    //         ..default()
+    //     })
+    //     .insert(Weapon);
+}
+
+fn spawn_player(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    spawn_pos: Vec2,
+    controls: Controls,
+) {
     let transform = Transform {
         translation: Vec3::new(spawn_pos.x, spawn_pos.y, 10.),
         scale: Vec3::new(1., 1., 1.),
@@ -31,169 +78,327 @@ fn player_spawn(mut commands: Commands, map: Res<Map>, asset_server: Res<AssetSe
 
     println!("Spawning player at {}, {}", spawn_pos.x, spawn_pos.y);
     commands
-        .spawn(SpriteBundle {
-            texture: asset_server.load("anim/idle/knight_m_idle_anim_f0.png"),
-            // sprite is 16x20
-            // scaled to 24x30
+        // the atlas and frame are set by `AnimationPlugin`
+        .spawn(SpriteSheetBundle {
             transform,
-            sprite: Sprite {
-                custom_size: Some(Vec2::new(16., 20.)),
-                ..default()
-            },
             ..default()
         })
         .insert(PlayerBundle::default())
+        .insert(controls)
+        .insert(Animation::new(asset_server.load(KNIGHT_ANIMATIONS), "idle"))
         .insert(RigidBody::from_transform(transform))
         .insert(Collider::from_position(
             transform.translation,
             Vec2::new(8., 10.),
-        ));
+        ))
+        .insert(PointLight2d {
+            color: Color::rgb(1., 0.9, 0.7),
+            radius: 180.,
+            intensity: 1.,
+        });
+}
 
-    // spawn with default weapon
-    // commands
-    //     .spawn(SpriteBundle {
-    //         material: materials.weapon_bow.clone(),
-    //         transform: Transform {
-    //             translation: Vec3::new(8.0, bottom + 75.0 / 4.0 + 10.0, EQUIPMENT_LAYER),
-    //             scale: Vec3::new(1.5, 1.5, 1.0),
-    //             ..default()
-    //         },
-    //         ..default()
-    //     })
-    //     .insert(Weapon);
+/// debug: F3 adds a second player on the same keyboard, splitting the keys between the two
+fn player_join(
+    mut commands: Commands,
+    kb: Res<Input<KeyCode>>,
+    map: Res<Map>,
+    asset_server: Res<AssetServer>,
+    mut query: Query<&mut Controls, With<Player>>,
+) {
+    if !kb.just_pressed(KeyCode::F3) || query.iter().count() != 1 {
+        return;
+    }
+
+    *query.single_mut() = Controls::left_hand();
+    let spawn_pos = map
+        .starting_positions
+        .get(1 % map.starting_positions.len().max(1))
+        .copied()
+        .unwrap_or(Vec2::ZERO);
+    println!("Player 2 joined");
+    spawn_player(
+        &mut commands,
+        &asset_server,
+        spawn_pos,
+        Controls::right_hand(),
+    );
+}
+
+/// returns the center x of a ladder overlapping the player, including one just below their feet
+fn ladder_at(map: &Map, rigidbody: &RigidBody, collider: &Collider) -> Option<f32> {
+    let feet = Vec2::new(rigidbody.position.x, rigidbody.position.y - 1.);
+    map.tiles_overlapping(feet, collider.half)
+        .into_iter()
+        .find(|(x, y)| map.is_ladder(*x, *y))
+        .map(|(x, y)| map.tile_position(x, y).x)
+}
+
+/// whether any of an action's keys is held
+fn held(kb: &Input<KeyCode>, keys: &[KeyCode]) -> bool {
+    kb.any_pressed(keys.iter().copied())
+}
+
+/// grab a ladder when pressing up or down while overlapping one
+fn try_climb(
+    kb: &Input<KeyCode>,
+    controls: &Controls,
+    map: &Map,
+    player: &mut Player,
+    rigidbody: &mut RigidBody,
+    collider: &Collider,
+) -> bool {
+    if held(kb, &controls.up) == held(kb, &controls.down) {
+        return false;
+    }
+    match ladder_at(map, rigidbody, collider) {
+        Some(ladder_x) => {
+            player.state = PlayerState::Climb;
+            rigidbody.speed = Vec3::ZERO;
+            rigidbody.position.x = ladder_x;
+            rigidbody.ignore_platforms = true;
+            rigidbody.on_ground = false;
+            true
+        }
+        None => false,
+    }
 }
 
 fn player_movement(
     kb: Res<Input<KeyCode>>,
     time: Res<Time>,
-    mut query: Query<(&Speed, &mut Player, &mut RigidBody, With<Player>)>,
+    map: Res<Map>,
+    mut query: Query<(&Speed, &mut Player, &mut RigidBody, &Collider, &Controls)>,
 ) {
-    let (speed, mut player, mut rigidbody, _) = query.single_mut();
-    match player.state {
-        PlayerState::Stand => {
-            rigidbody.speed = Vec3::ZERO;
+    let kb = kb.as_ref();
+    for (speed, mut player, mut rigidbody, collider, controls) in query.iter_mut() {
+        match player.state {
+            PlayerState::Stand => {
+                rigidbody.speed = Vec3::ZERO;
+
+                if try_climb(kb, controls, &map, &mut player, &mut rigidbody, collider) {
+                    continue;
+                }
+
+                if !rigidbody.on_ground {
+                    player.state = PlayerState::Jump;
+                    continue;
+                }
 
-            if !rigidbody.on_ground {
-                player.state = PlayerState::Jump;
-                return;
+                // if left or right pressed, not both
+                if held(kb, &controls.left) != held(kb, &controls.right) {
+                    player.state = PlayerState::Walk;
+                    continue;
+                // if jump pressed
+                } else if held(kb, &controls.jump) && !rigidbody.at_ceiling {
+                    rigidbody.speed.y = player.jump_speed;
+                    player.state = PlayerState::Jump;
+                // if drop pressed
+                } else if held(kb, &controls.down) {
+                    if rigidbody.on_platform {
+                        rigidbody.position.y -= PLATFORM_THRESHOLD;
+                    }
+                }
             }
+            PlayerState::Walk => {
+                if try_climb(kb, controls, &map, &mut player, &mut rigidbody, collider) {
+                    continue;
+                }
 
-            // if left or right pressed, not both
-            if (kb.pressed(KeyCode::Left) || kb.pressed(KeyCode::A))
-                != (kb.pressed(KeyCode::Right) || kb.pressed(KeyCode::D))
-            {
-                player.state = PlayerState::Walk;
-                return;
-            // if jump pressed
-            } else if kb.pressed(KeyCode::Space) && !rigidbody.at_ceiling {
-                rigidbody.speed.y = player.jump_speed;
-                player.state = PlayerState::Jump;
-            // if drop pressed
-            } else if kb.pressed(KeyCode::Down) || kb.pressed(KeyCode::S) {
-                if rigidbody.on_platform {
-                    rigidbody.position.y -= PLATFORM_THRESHOLD;
+                // if both left and right pressed, or no keys pressed, stop
+                if held(kb, &controls.left) == held(kb, &controls.right) {
+                    player.state = PlayerState::Stand;
+                    rigidbody.speed = Vec3::ZERO;
+                // go right
+                } else if held(kb, &controls.right) {
+                    if rigidbody.at_right_tile {
+                        rigidbody.speed.x = 0.;
+                    } else {
+                        rigidbody.speed.x = speed.0.x;
+                    }
+                    player.facing = Direction::Right;
+                // go left
+                } else if held(kb, &controls.left) {
+                    if rigidbody.at_left_tile {
+                        rigidbody.speed.x = 0.;
+                    } else {
+                        rigidbody.speed.x = -speed.0.x;
+                    }
+                    player.facing = Direction::Left;
+                // if drop pressed
+                } else if held(kb, &controls.down) {
+                    if rigidbody.on_platform {
+                        rigidbody.position.y -= PLATFORM_THRESHOLD;
+                    }
+                }
+                // if theres no tile to walk on, fall
+                if held(kb, &controls.jump) && !rigidbody.at_ceiling {
+                    rigidbody.speed.y = player.jump_speed;
+                    player.state = PlayerState::Jump;
+                } else if !rigidbody.on_ground {
+                    player.state = PlayerState::Jump;
                 }
             }
-        }
-        PlayerState::Walk => {
-            // if both left and right pressed, or no keys pressed, stop
-            if (kb.pressed(KeyCode::Left) || kb.pressed(KeyCode::A))
-                == (kb.pressed(KeyCode::Right) || kb.pressed(KeyCode::D))
-            {
-                player.state = PlayerState::Stand;
+            PlayerState::Climb => {
                 rigidbody.speed = Vec3::ZERO;
-            // go right
-            } else if kb.pressed(KeyCode::Right) || kb.pressed(KeyCode::D) {
-                if rigidbody.at_right_tile {
-                    rigidbody.speed.x = 0.;
-                } else {
-                    rigidbody.speed.x = speed.0.x;
+
+                // jump off the ladder
+                if held(kb, &controls.jump) {
+                    rigidbody.ignore_platforms = false;
+                    rigidbody.speed.y = player.min_jump_speed;
+                    player.state = PlayerState::Jump;
+                    continue;
                 }
-                rigidbody.scale.x = rigidbody.scale.x.abs();
-                player.facing = Direction::Right;
-            // go left
-            } else if kb.pressed(KeyCode::Left) || kb.pressed(KeyCode::A) {
-                if rigidbody.at_left_tile {
-                    rigidbody.speed.x = 0.;
+
+                // if both up and down pressed, or no keys pressed, hold on
+                if held(kb, &controls.up) == held(kb, &controls.down) {
+                    rigidbody.speed.y = 0.;
+                // go up
+                } else if held(kb, &controls.up) {
+                    if !rigidbody.at_ceiling {
+                        rigidbody.speed.y = player.climb_speed;
+                    }
+                // go down
                 } else {
-                    rigidbody.speed.x = -speed.0.x;
+                    rigidbody.speed.y = -player.climb_speed;
                 }
-                rigidbody.scale.x = -rigidbody.scale.x.abs();
-                player.facing = Direction::Left;
-            // if drop pressed
-            } else if kb.pressed(KeyCode::Down) || kb.pressed(KeyCode::S) {
-                if rigidbody.on_platform {
-                    rigidbody.position.y -= PLATFORM_THRESHOLD;
+
+                // climbed off the top (and land on it), or off the bottom of the ladder
+                if ladder_at(&map, &rigidbody, collider).is_none() {
+                    rigidbody.ignore_platforms = false;
+                    rigidbody.speed.y = 0.;
+                    player.state = PlayerState::Jump;
+                // reached the ground
+                } else if rigidbody.on_ground && rigidbody.speed.y < 0. {
+                    rigidbody.ignore_platforms = false;
+                    rigidbody.speed.y = 0.;
+                    player.state = PlayerState::Stand;
                 }
             }
-            // if theres no tile to walk on, fall
-            if kb.pressed(KeyCode::Space) && !rigidbody.at_ceiling {
-                rigidbody.speed.y = player.jump_speed;
-                player.state = PlayerState::Jump;
-            } else if !rigidbody.on_ground {
-                player.state = PlayerState::Jump;
-            }
-        }
-        PlayerState::Jump => {
-            rigidbody.speed.y += GRAVITY * time.delta_seconds();
-            if rigidbody.speed.y < MAX_FALLING_SPEED {
-                rigidbody.speed.y = MAX_FALLING_SPEED;
+            PlayerState::Dead => {
+                rigidbody.speed = Vec3::ZERO;
             }
+            PlayerState::Jump => {
+                if held(kb, &controls.up)
+                    && try_climb(kb, controls, &map, &mut player, &mut rigidbody, collider)
+                {
+                    continue;
+                }
 
-            if rigidbody.at_ceiling || (!kb.pressed(KeyCode::Space) && rigidbody.speed.y > 0.) {
-                if rigidbody.speed.y > player.min_jump_speed {
-                    rigidbody.speed.y = player.min_jump_speed;
+                rigidbody.speed.y += GRAVITY * time.delta_seconds();
+                if rigidbody.speed.y < MAX_FALLING_SPEED {
+                    rigidbody.speed.y = MAX_FALLING_SPEED;
                 }
-            }
 
-            // stop moving
-            if (kb.pressed(KeyCode::Left) || kb.pressed(KeyCode::A))
-                == (kb.pressed(KeyCode::Right) || kb.pressed(KeyCode::D))
-            {
-                rigidbody.speed.x = 0.;
-            // go right
-            } else if kb.pressed(KeyCode::Right) || kb.pressed(KeyCode::D) {
-                if rigidbody.at_right_tile {
-                    rigidbody.speed.x = 0.;
-                } else {
-                    rigidbody.speed.x = speed.0.x;
+                if rigidbody.at_ceiling || (!held(kb, &controls.jump) && rigidbody.speed.y > 0.) {
+                    if rigidbody.speed.y > player.min_jump_speed {
+                        rigidbody.speed.y = player.min_jump_speed;
+                    }
                 }
-                rigidbody.scale.x = rigidbody.scale.x.abs();
-                player.facing = Direction::Right;
-            // go left
-            } else if kb.pressed(KeyCode::Left) || kb.pressed(KeyCode::A) {
-                if rigidbody.at_left_tile {
+
+                // stop moving
+                if held(kb, &controls.left) == held(kb, &controls.right) {
                     rigidbody.speed.x = 0.;
-                } else {
-                    rigidbody.speed.x = -speed.0.x;
+                // go right
+                } else if held(kb, &controls.right) {
+                    if rigidbody.at_right_tile {
+                        rigidbody.speed.x = 0.;
+                    } else {
+                        rigidbody.speed.x = speed.0.x;
+                    }
+                    player.facing = Direction::Right;
+                // go left
+                } else if held(kb, &controls.left) {
+                    if rigidbody.at_left_tile {
+                        rigidbody.speed.x = 0.;
+                    } else {
+                        rigidbody.speed.x = -speed.0.x;
+                    }
+                    player.facing = Direction::Left;
                 }
-                rigidbody.scale.x = -rigidbody.scale.x.abs();
-                player.facing = Direction::Left;
-            }
 
-            if rigidbody.on_ground {
-                if (kb.pressed(KeyCode::Left) || kb.pressed(KeyCode::A))
-                    == (kb.pressed(KeyCode::Right) || kb.pressed(KeyCode::D))
-                {
-                    player.state = PlayerState::Stand;
-                    rigidbody.speed = Vec3::ZERO;
-                } else {
-                    player.state = PlayerState::Walk;
-                    rigidbody.speed.y = 0.;
+                if rigidbody.on_ground {
+                    if held(kb, &controls.left) == held(kb, &controls.right) {
+                        player.state = PlayerState::Stand;
+                        rigidbody.speed = Vec3::ZERO;
+                    } else {
+                        player.state = PlayerState::Walk;
+                        rigidbody.speed.y = 0.;
+                    }
                 }
             }
         }
     }
 }
 
+/// start an attack, once per press, which fires when the animation gets to it
 fn player_attack(
     mut commands: Commands,
     kb: Res<Input<KeyCode>>,
-    mut query: Query<(&Transform, &mut PlayerReadyAttack, &Player, With<Player>)>,
+    mut query: Query<(
+        Entity,
+        &mut PlayerReadyAttack,
+        &Player,
+        &Controls,
+        Option<&Attacking>,
+    )>,
+) {
+    for (entity, mut ready_attack, player, controls, attacking) in query.iter_mut() {
+        if kb.any_just_released(controls.attack.iter().copied()) {
+            ready_attack.0 = true;
+        }
+        if player.state == PlayerState::Dead || attacking.is_some() {
+            continue;
+        }
+        if ready_attack.0 && held(&kb, &controls.attack) {
+            commands.entity(entity).insert(Attacking);
+            ready_attack.0 = false;
+        }
+    }
+}
+
+/// without an attack animation to wait for, fire and finish attacks straight away
+fn player_attack_fallback(
+    sets: Res<Assets<AnimationSet>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    mut ev_animation: EventWriter<AnimationEvent>,
+    query: Query<(Entity, Option<&Animation>), With<Attacking>>,
+) {
+    for (entity, animation) in query.iter() {
+        if let Some(animation) = animation {
+            if animation.can_play("attack", &sets, &atlases) {
+                continue;
+            }
+        }
+        for name in [ATTACK_RELEASE, ATTACK_END] {
+            ev_animation.send(AnimationEvent {
+                entity,
+                name: name.to_string(),
+            });
+        }
+    }
+}
+
+/// fire when the attack animation releases, and finish the attack at its end
+fn player_attack_events(
+    mut commands: Commands,
+    mut ev_animation: EventReader<AnimationEvent>,
+    query: Query<(&Transform, &Player), With<Attacking>>,
     asset_server: Res<AssetServer>,
 ) {
-    let (player_tf, mut ready_attack, player, _) = query.single_mut();
-    if ready_attack.0 && kb.pressed(KeyCode::Return) {
+    for ev in ev_animation.iter() {
+        let (player_tf, player) = match query.get(ev.entity) {
+            Ok(player) => player,
+            Err(_) => continue,
+        };
+        if ev.name == ATTACK_END {
+            commands.entity(ev.entity).remove::<Attacking>();
+            continue;
+        }
+        if ev.name != ATTACK_RELEASE {
+            continue;
+        }
+
         let x = player_tf.translation.x;
         let y = player_tf.translation.y;
         commands
@@ -218,19 +423,244 @@ fn player_attack(
             .insert(Projectile {
                 direction: player.facing,
             })
-            .insert(Speed::new(1000., 1000.));
-        ready_attack.0 = false;
+            .insert(Speed::new(1000., 1000.))
+            .insert(PointLight2d {
+                color: Color::rgb(0.6, 0.8, 1.),
+                radius: 48.,
+                intensity: 0.6,
+            });
+        // muzzle flash
+        commands.spawn((
+            Flash {
+                timer: Timer::from_seconds(FLASH_DURATION, TimerMode::Once),
+                intensity: 1.,
+            },
+            PointLight2d {
+                color: Color::rgb(1., 0.85, 0.5),
+                radius: 120.,
+                intensity: 1.,
+            },
+            TransformBundle::from_transform(Transform::from_xyz(x, y, 0.)),
+        ));
+    }
+}
 
-        if kb.just_released(KeyCode::Return) {
-            ready_attack.0 = true;
+/// how much landing at a speed shakes the camera, nothing for the usual jumps
+fn landing_trauma(speed: f32) -> f32 {
+    let hardness = (-speed - LANDING_SHAKE_SPEED) / (-MAX_FALLING_SPEED - LANDING_SHAKE_SPEED);
+    hardness.clamp(0., 1.) * 0.5
+}
+
+/// shake the camera when a player hits the ground hard
+fn player_landing(
+    mut falling: Local<HashMap<Entity, f32>>,
+    mut ev_effects: EventWriter<CameraEffect>,
+    query: Query<(Entity, &RigidBody), With<Player>>,
+) {
+    for (entity, rigidbody) in query.iter() {
+        if !rigidbody.on_ground {
+            // the ground stops the body before the landing is seen, so remember the speed
+            falling.insert(entity, rigidbody.speed.y);
+        } else if let Some(speed) = falling.remove(&entity) {
+            let trauma = landing_trauma(speed);
+            if trauma > 0. {
+                ev_effects.send(CameraEffect::Shake(trauma));
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn player_death(
+    mut commands: Commands,
+    mut ev_effects: EventWriter<CameraEffect>,
+    mut query: Query<
+        (
+            Entity,
+            &Health,
+            &mut Player,
+            &mut RigidBody,
+            &mut TextureAtlasSprite,
+        ),
+        Without<Dying>,
+    >,
+) {
+    for (entity, health, mut player, mut rigidbody, mut sprite) in query.iter_mut() {
+        if !health.is_dead() {
+            continue;
         }
+
+        println!("Player died");
+        ev_effects.send(CameraEffect::Shake(0.8));
+        ev_effects.send(CameraEffect::ZoomPunch(0.15));
+        player.state = PlayerState::Dead;
+        rigidbody.speed = Vec3::ZERO;
+        sprite.color = Color::rgb(1., 0.3, 0.3);
+        // the attack animation won't finish
+        commands.entity(entity).remove::<Attacking>().insert(Dying {
+            timer: Timer::from_seconds(DEATH_DURATION, TimerMode::Once),
+        });
+    }
+}
+
+/// where a player respawns: their last checkpoint if it's in this level and still in the map,
+/// otherwise one of the starting positions
+fn respawn_position(map: &Map, campaign: &Campaign, respawn: &RespawnPoint) -> Vec2 {
+    let checkpoint = respawn.0.as_ref().filter(|(level, x, y)| {
+        level == campaign.current_path()
+            && map
+                .spawns
+                .iter()
+                .any(|s| s.kind == SpawnKind::Checkpoint && s.x == *x && s.y == *y)
+    });
+    match checkpoint {
+        Some((_, x, y)) => map.tile_position(*x, *y),
+        None => map
+            .starting_positions
+            .choose(&mut rand::thread_rng())
+            .copied()
+            .unwrap_or(Vec2::ZERO),
     }
 }
 
+#[allow(clippy::type_complexity)]
+fn player_respawn(
+    mut commands: Commands,
+    time: Res<Time>,
+    map: Res<Map>,
+    campaign: Res<Campaign>,
+    mut query: Query<(
+        Entity,
+        &RespawnPoint,
+        &mut Dying,
+        &mut Health,
+        &mut Player,
+        &mut RigidBody,
+        &mut TextureAtlasSprite,
+    )>,
+) {
+    for (entity, respawn, mut dying, mut health, mut player, mut rigidbody, mut sprite) in
+        query.iter_mut()
+    {
+        // fade out while dying
+        dying.timer.tick(time.delta());
+        sprite.color.set_a(1. - dying.timer.percent());
+        if !dying.timer.finished() {
+            continue;
+        }
+
+        let spawn_pos = respawn_position(&map, &campaign, respawn);
+        println!("Respawning player at {}, {}", spawn_pos.x, spawn_pos.y);
+
+        place_body(&mut rigidbody, spawn_pos);
+        health.reset();
+        player.state = PlayerState::Jump;
+        sprite.color = Color::WHITE;
+        commands
+            .entity(entity)
+            .remove::<Dying>()
+            .insert(Invulnerable {
+                timer: Timer::from_seconds(RESPAWN_INVULNERABILITY, TimerMode::Once),
+            });
+    }
+}
+
+/// puts a body at rest at a new position, forgetting whatever it was standing on or climbing
+fn place_body(rigidbody: &mut RigidBody, position: Vec2) {
+    rigidbody.position = Vec3::new(position.x, position.y, rigidbody.position.z);
+    rigidbody.old_position = rigidbody.position;
+    rigidbody.speed = Vec3::ZERO;
+    rigidbody.on_ground = false;
+    rigidbody.on_platform = false;
+    rigidbody.ignore_platforms = false;
+}
+
+/// players blink while they're invulnerable
+fn player_invulnerability(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Invulnerable, &mut TextureAtlasSprite)>,
+) {
+    for (entity, mut invulnerable, mut sprite) in query.iter_mut() {
+        invulnerable.timer.tick(time.delta());
+        if invulnerable.timer.finished() {
+            sprite.color.set_a(1.);
+            commands.entity(entity).remove::<Invulnerable>();
+            continue;
+        }
+        let visible = (invulnerable.timer.elapsed_secs() * 10.) as i32 % 2 == 0;
+        sprite.color.set_a(if visible { 1. } else { 0.3 });
+    }
+}
+
+/// move players to the starting positions of a newly loaded level
+fn player_level_start(
+    mut ev_loaded: EventReader<LevelLoaded>,
+    map: Res<Map>,
+    mut query: Query<(&mut Player, &mut RigidBody)>,
+) {
+    let level = match ev_loaded.iter().last() {
+        Some(ev) => ev.index,
+        None => return,
+    };
+
+    for (i, (mut player, mut rigidbody)) in query.iter_mut().enumerate() {
+        let spawn_pos = map
+            .starting_positions
+            .get(i % map.starting_positions.len().max(1))
+            .copied()
+            .unwrap_or(Vec2::ZERO);
+        println!(
+            "Moving player to {}, {} for level {}",
+            spawn_pos.x, spawn_pos.y, level
+        );
+
+        place_body(&mut rigidbody, spawn_pos);
+        if player.state != PlayerState::Dead {
+            player.state = PlayerState::Jump;
+        }
+    }
+}
+
+/// keep players where they are when the level file is reloaded, unless they're now inside a wall
+fn player_map_reloaded(
+    mut ev_reloaded: EventReader<MapReloaded>,
+    map: Res<Map>,
+    mut query: Query<(&mut RigidBody, &Collider), With<Player>>,
+) {
+    if ev_reloaded.iter().last().is_none() {
+        return;
+    }
+
+    for (mut rigidbody, collider) in query.iter_mut() {
+        let mut pos = rigidbody.position.truncate();
+        for _ in 0..map.height {
+            let in_wall = map
+                .tiles_overlapping(pos, collider.half)
+                .iter()
+                .any(|(x, y)| map.is_obstacle(*x, *y));
+            if !in_wall {
+                break;
+            }
+            pos.y += map.tile_size as f32;
+        }
+        if pos != rigidbody.position.truncate() {
+            println!("Nudging player out of wall to {}, {}", pos.x, pos.y);
+            rigidbody.position = Vec3::new(pos.x, pos.y, rigidbody.position.z);
+            rigidbody.old_position = rigidbody.position;
+            rigidbody.speed = Vec3::ZERO;
+        }
+    }
+}
+
+/// moves projectiles until they're out of view or hit a wall (damaging breakable ones) or a
+/// switch (flipping it)
 fn projectile_movement(
     mut commands: Commands,
-    win_size: Res<WinSize>,
     time: Res<Time>,
+    map: Res<Map>,
+    mut ev_damage: EventWriter<TerrainDamage>,
+    mut ev_toggle: EventWriter<ToggleSwitch>,
     mut query: Query<(
         Entity,
         &Projectile,
@@ -241,6 +671,7 @@ fn projectile_movement(
 ) {
     for (proj_entity, projectile, proj_speed, mut proj_tf, _) in query.iter_mut() {
         let translation = &mut proj_tf.translation;
+        let start = translation.truncate();
         if projectile.direction == Direction::Right {
             translation.x += proj_speed.0.x * time.delta_seconds();
         } else if projectile.direction == Direction::Left {
@@ -250,12 +681,99 @@ fn projectile_movement(
         } else if projectile.direction == Direction::Down {
             translation.y -= proj_speed.0.y * time.delta_seconds();
         }
-        if translation.x > win_size.w / 2.
-            || translation.x < -win_size.w / 2.
-            || translation.y > win_size.h / 2.
-            || translation.y < -win_size.h / 2.
-        {
+        // gone once they leave the map
+        let (min, max) = map.bounds();
+        if translation.truncate().cmplt(min).any() || translation.truncate().cmpgt(max).any() {
             commands.entity(proj_entity).despawn();
+            continue;
         }
+
+        // check every half tile along the way so fast projectiles can't skip a wall
+        let end = translation.truncate();
+        let steps = ((end - start).length() / (map.tile_size as f32 / 2.))
+            .ceil()
+            .max(1.);
+        for i in 1..=steps as i32 {
+            let pos = start.lerp(end, i as f32 / steps);
+            let (x, y) = map.tile_coords(pos);
+            if map.is_switch(x, y) {
+                ev_toggle.send(ToggleSwitch { x, y });
+                commands.entity(proj_entity).despawn();
+                break;
+            }
+            if map.in_bounds(x, y) && map.is_obstacle(x, y) {
+                if map.is_breakable(x, y) {
+                    ev_damage.send(TerrainDamage {
+                        pos,
+                        radius: 0.,
+                        amount: 1,
+                    });
+                }
+                commands.entity(proj_entity).despawn();
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Campaign;
+
+    #[test]
+    fn hard_landings() {
+        // a jump lands at about its take off speed
+        assert_eq!(landing_trauma(-Player::default().jump_speed), 0.);
+        assert_eq!(landing_trauma(0.), 0.);
+        assert!(landing_trauma(-700.) > 0.);
+        assert_eq!(landing_trauma(MAX_FALLING_SPEED), 0.5);
+    }
+
+    #[test]
+    fn placed_bodies_stop_climbing() {
+        // died while climbing down through a ladder top
+        let mut rigidbody = RigidBody {
+            position: Vec3::new(10., 20., 1.),
+            speed: Vec3::new(0., -100., 0.),
+            on_ground: true,
+            on_platform: true,
+            ignore_platforms: true,
+            ..default()
+        };
+        place_body(&mut rigidbody, Vec2::new(-5., 7.));
+        assert_eq!(rigidbody.position, Vec3::new(-5., 7., 1.));
+        assert_eq!(rigidbody.old_position, rigidbody.position);
+        assert_eq!(rigidbody.speed, Vec3::ZERO);
+        assert!(!rigidbody.on_ground);
+        assert!(!rigidbody.on_platform);
+        assert!(!rigidbody.ignore_platforms);
+    }
+
+    #[test]
+    fn respawn_at_checkpoint() {
+        let map = Map::from_ascii(
+            "
+            X---P-
+            ######
+            ",
+        );
+        let campaign = Campaign {
+            levels: vec!["maps/01.ron".into(), "maps/02.ron".into()],
+            current: 0,
+        };
+        let start = map.starting_positions[0];
+        let checkpoint = map.tile_position(4, 1);
+
+        let respawn = RespawnPoint::default();
+        assert_eq!(respawn_position(&map, &campaign, &respawn), start);
+        let respawn = RespawnPoint(Some(("maps/01.ron".into(), 4, 1)));
+        assert_eq!(respawn_position(&map, &campaign, &respawn), checkpoint);
+        // in another level
+        let respawn = RespawnPoint(Some(("maps/02.ron".into(), 4, 1)));
+        assert_eq!(respawn_position(&map, &campaign, &respawn), start);
+        // no longer a checkpoint there
+        let respawn = RespawnPoint(Some(("maps/01.ron".into(), 3, 1)));
+        assert_eq!(respawn_position(&map, &campaign, &respawn), start);
     }
 }
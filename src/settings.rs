@@ -0,0 +1,86 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::assets::asset_path;
+
+pub const DEFAULT_SETTINGS: &str = "settings.ron";
+
+/// how the view is shared between players
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum CameraMode {
+    /// one camera, zoomed out to keep every player in view
+    #[default]
+    Shared,
+    /// a viewport per player
+    Split,
+}
+
+/// options read from `assets/settings.ron`, anything missing is left as the default
+#[derive(Resource, Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub camera_mode: CameraMode,
+    /// how much the view shakes, and zooms in on big hits: 0 turns it off
+    pub screen_shake: f32,
+    /// whether the screen flashes when hurt
+    pub screen_flashes: bool,
+    /// start in fullscreen, F11 switches while playing
+    pub fullscreen: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            camera_mode: CameraMode::default(),
+            screen_shake: 1.,
+            screen_flashes: true,
+            fullscreen: false,
+        }
+    }
+}
+
+impl Settings {
+    /// the settings in a file, or the defaults if it can't be read
+    pub fn load(path: &str) -> Self {
+        let full_path = asset_path(path);
+        let contents = match std::fs::read_to_string(&full_path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                println!(
+                    "Using default settings, unable to read {:?}: {}",
+                    full_path, e
+                );
+                return Self::default();
+            }
+        };
+        ron::from_str(&contents).unwrap_or_else(|e| {
+            println!(
+                "Using default settings, unable to parse {:?}: {}",
+                full_path, e
+            );
+            Self::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let settings: Settings = ron::from_str("(camera_mode: Split)").unwrap();
+        assert_eq!(settings.camera_mode, CameraMode::Split);
+        let settings: Settings = ron::from_str("()").unwrap();
+        assert_eq!(settings.camera_mode, CameraMode::Shared);
+        assert_eq!(settings.screen_shake, 1.);
+        assert!(settings.screen_flashes);
+        let settings: Settings =
+            ron::from_str("(screen_shake: 0., screen_flashes: false)").unwrap();
+        assert_eq!(settings.screen_shake, 0.);
+        assert!(!settings.screen_flashes);
+        assert!(!settings.fullscreen);
+        let settings: Settings = ron::from_str("(fullscreen: true)").unwrap();
+        assert!(settings.fullscreen);
+    }
+}